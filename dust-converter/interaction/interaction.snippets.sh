@@ -59,16 +59,55 @@ resume() {
 # . ./interaction.snippets.sh && addKnownTokens WEGLD-d7c6bb RIDE-6e4c49 erd1qqqqqqqqqqqqqpgqe8m9w7cv2ekdc28q5ahku9x3hcregqpn0n4sum0e3u 0x016345785d8a0000
 # . ./interaction.snippets.sh && addKnownTokens USDC-8d4068 ABC-667e0a erd1qqqqqqqqqqqqqpgqyh76uvply5j9pwczjfcpfqeg4973l3xsmtvsh94f02 0x016345785d8a0000
 # . ./interaction.snippets.sh && addKnownTokens USDC-8d4068 ETHX-ea59cc erd1qqqqqqqqqqqqqpgqhxjgc7m8uy2umz2jutxaev6swf8j5kunmtvs3fq38j 0x016345785d8a0000
+# Registers a token with a single hop route (token -> output token) through the given pair
 addKnownTokens() {
     output_token_id=$1
     token_id=$2
+    pair_address="$(erdpy wallet bech32 --decode $3)"
+    route="0x${pair_address}$(printf '%08x' ${#output_token_id})$(echo -n $output_token_id | xxd -p | tr -d '\n')"
 
     erdpy --verbose contract call ${DUST_CONVERTER_ADDRESS} --recall-nonce \
         --pem=${WALLET_PEM} \
         --proxy=${PROXY} --chain=${CHAIN_ID} \
         --gas-limit=10000000 \
         --function=addKnownTokens \
-        --arguments str:$output_token_id str:$token_id $3 $4 \
+        --arguments str:$output_token_id str:$token_id $route $4 \
+        --send || return
+}
+
+# A base token must be registered under WEGLD first, its route there is used to swap it to WEGLD
+# . ./interaction.snippets.sh && addBaseTokens USDT-188935
+addBaseTokens() {
+    erdpy --verbose contract call ${DUST_CONVERTER_ADDRESS} --recall-nonce \
+        --pem=${WALLET_PEM} \
+        --proxy=${PROXY} --chain=${CHAIN_ID} \
+        --gas-limit=10000000 \
+        --function=addBaseTokens \
+        --arguments str:$1 \
+        --send || return
+}
+
+# Route hops given with the zero address are looked up in the router
+# . ./interaction.snippets.sh && setRouterAddress erd1qqqqqqqqqqqqqpgqq66xk9gfr4esuhem3jru86wg5hvp33a62jps2fy57p
+setRouterAddress() {
+    erdpy --verbose contract call ${DUST_CONVERTER_ADDRESS} --recall-nonce \
+        --pem=${WALLET_PEM} \
+        --proxy=${PROXY} --chain=${CHAIN_ID} \
+        --gas-limit=10000000 \
+        --function=setRouterAddress \
+        --arguments $1 \
+        --send || return
+}
+
+# Allows swapDustTokensAndCall to send its output to the endpoint of the target contract
+# . ./interaction.snippets.sh && addCallTarget erd1qqqqqqqqqqqqqpgqq66xk9gfr4esuhem3jru86wg5hvp33a62jps2fy57p stakeFor
+addCallTarget() {
+    erdpy --verbose contract call ${DUST_CONVERTER_ADDRESS} --recall-nonce \
+        --pem=${WALLET_PEM} \
+        --proxy=${PROXY} --chain=${CHAIN_ID} \
+        --gas-limit=10000000 \
+        --function=addCallTarget \
+        --arguments $1 str:$2 \
         --send || return
 }
 
@@ -85,6 +124,41 @@ removeKnownTokens() {
         --send || return
 }
 
+# Run once per output token after upgrading from the registry layout prior to TokenConfig
+# . ./interaction.snippets.sh && migrateTokenRegistry WEGLD-d7c6bb
+migrateTokenRegistry() {
+    erdpy --verbose contract call ${DUST_CONVERTER_ADDRESS} --recall-nonce \
+        --pem=${WALLET_PEM} \
+        --proxy=${PROXY} --chain=${CHAIN_ID} \
+        --gas-limit=50000000 \
+        --function=migrateTokenRegistry \
+        --arguments str:$1 \
+        --send || return
+}
+
+# Run after upgrading from the fee layout prior to per-token fees, with the tags holding fees
+# . ./interaction.snippets.sh && migrateFeeStorage TAG1 TAG2
+migrateFeeStorage() {
+    erdpy --verbose contract call ${DUST_CONVERTER_ADDRESS} --recall-nonce \
+        --pem=${WALLET_PEM} \
+        --proxy=${PROXY} --chain=${CHAIN_ID} \
+        --gas-limit=50000000 \
+        --function=migrateFeeStorage \
+        --arguments $(for tag in "$@"; do echo -n "str:$tag "; done) \
+        --send || return
+}
+
+# Run once after migrateFeeStorage to seed the WEGLD reserve with the balance that is not fees
+# . ./interaction.snippets.sh && migrateReserve
+migrateReserve() {
+    erdpy --verbose contract call ${DUST_CONVERTER_ADDRESS} --recall-nonce \
+        --pem=${WALLET_PEM} \
+        --proxy=${PROXY} --chain=${CHAIN_ID} \
+        --gas-limit=10000000 \
+        --function=migrateReserve \
+        --send || return
+}
+
 # . ./interaction.snippets.sh && sellDustTokens
 sellDustTokens() {
     erdpy --verbose contract call ${DUST_CONVERTER_ADDRESS} --recall-nonce \
@@ -1,14 +1,39 @@
+use crate::{events, proxy, reserve};
+use crate::sell::{SellCursor, DEFAULT_SELL_MIN_GAS_LEFT};
+use permissions_module::Permissions;
+use proxy::{min_amount, PairState};
+
 elrond_wasm::imports!();
 elrond_wasm::derive_imports!();
 
-pub type AddKnownTokenType<M> = MultiValue3<TokenIdentifier<M>, ManagedAddress<M>, BigUint<M>>;
+pub type Route<M> = ManagedVec<M, PairContractData<M>>;
+pub type AddKnownTokenType<M> = MultiValue3<TokenIdentifier<M>, Route<M>, BigUint<M>>;
 
-#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, Clone, PartialEq, Debug)]
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, ManagedVecItem, Clone, PartialEq, Debug)]
 pub struct PairContractData<M: ManagedTypeApi> {
     pub address: ManagedAddress<M>,
     pub output_token: TokenIdentifier<M>,
 }
 
+/// Registry entry of a token that can be swapped. route is the full route, its first hop being the
+/// token's pair. Disabled tokens keep their configuration but are neither accepted nor sold.
+/// Zero caps are disabled.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, Clone, PartialEq, Debug)]
+pub struct TokenConfig<M: ManagedTypeApi> {
+    pub enabled: bool,
+    pub output_token: TokenIdentifier<M>,
+    pub route: Route<M>,
+    pub threshold: BigUint<M>,
+    pub max_swap_value: BigUint<M>,
+    pub epoch_inflow_cap: BigUint<M>,
+}
+
+#[derive(TopEncode, TopDecode, TypeAbi, Clone, PartialEq, Debug)]
+pub struct EpochInflow<M: ManagedTypeApi> {
+    pub epoch: u64,
+    pub amount: BigUint<M>,
+}
+
 pub const MAX_PERCENTAGE: u64 = 10_000u64;
 pub const MAX_FEE_PERCENTAGE: u64 = 9_000u64;
 
@@ -16,26 +41,39 @@ pub const MAX_FEE_PERCENTAGE: u64 = 9_000u64;
 pub trait ConfigModule:
     permissions_module::PermissionsModule
     + pausable::PausableModule
+    + proxy::ProxyModule
+    + reserve::ReserveModule
+    + events::EventsModule
 {
 
     #[payable("*")]
     #[endpoint(topUp)]
     fn top_up(&self) {
         self.require_caller_has_owner_or_admin_permissions();
-        let (token_id, _) = self.call_value().single_fungible_esdt();
-        require!(token_id == self.wrapped_token().get(), "Invalid token");
+        let (token_id, amount) = self.call_value().single_fungible_esdt();
+        require!(self.output_tokens().contains(&token_id), "Invalid token");
+
+        self.deposit_reserve(&token_id, &amount);
+        self.top_up_event(&self.blockchain().get_caller(), &token_id, &amount);
     }
 
+    /// Sends all the collected protocol fees to the owner. The WEGLD fees are sent as EGLD if unwrap_egld is set.
     #[endpoint(extractFees)]
-    fn extract_fees(&self) {
+    fn extract_fees(&self, unwrap_egld: OptionalValue<bool>) {
         self.require_caller_has_owner_permissions();
 
-        let owner = self.blockchain().get_caller();
-        let wrapped_token = self.wrapped_token().get();
-        let fee_amount = self.collected_fee_amount().get();
-        self.send().direct_esdt(&owner, &wrapped_token, 0, &fee_amount);
+        let mut fees = ManagedVec::new();
+        for token in self.fee_tokens().iter() {
+            let fee_amount = self.collected_fee_amount(&token).get();
+            if fee_amount > 0 {
+                fees.push(EsdtTokenPayment::new(token.clone(), 0, fee_amount));
+                self.collected_fee_amount(&token).clear();
+            }
+        }
 
-        self.collected_fee_amount().set(BigUint::zero());
+        let owner = self.blockchain().get_caller();
+        self.send_payments(&owner, &fees, unwrap_egld.into_option().unwrap_or_default());
+        self.extract_fees_event(&owner, &fees);
     }
 
     #[endpoint(setFeePercentage)]
@@ -44,6 +82,32 @@ pub trait ConfigModule:
         require!(protocol_fee < MAX_FEE_PERCENTAGE, "Fee percent invalid");
 
         self.protocol_fee_percent().set(protocol_fee);
+        self.protocol_fee_percentage_event(protocol_fee);
+    }
+
+    #[endpoint(setWrappingContract)]
+    fn set_wrapping_contract(&self, address: ManagedAddress) {
+        self.require_caller_has_owner_or_admin_permissions();
+        require!(
+            self.blockchain().is_smart_contract(&address),
+            "Invalid SC address"
+        );
+
+        self.wrapping_contract_event(&address);
+        self.wrapping_contract().set(address);
+    }
+
+    /// Sets the DEX router that route hops without a pair address are looked up in.
+    #[endpoint(setRouterAddress)]
+    fn set_router_address(&self, address: ManagedAddress) {
+        self.require_caller_has_owner_or_admin_permissions();
+        require!(
+            self.blockchain().is_smart_contract(&address),
+            "Invalid SC address"
+        );
+
+        self.router_address_event(&address);
+        self.router_address().set(address);
     }
 
     #[endpoint(setSlippagePercentage)]
@@ -52,75 +116,708 @@ pub trait ConfigModule:
         require!(slippage < MAX_FEE_PERCENTAGE, "Slippage percent invalid");
 
         self.slippage_percent().set(slippage);
+        self.slippage_percentage_event(slippage);
+    }
+
+    /// When enabled, every swapped payment is sold in the same transaction and the user is paid from the proceeds.
+    #[endpoint(setAtomicSwapMode)]
+    fn set_atomic_swap_mode(&self, enabled: bool) {
+        self.require_caller_has_owner_or_admin_permissions();
+
+        self.atomic_swap_mode().set(enabled);
+        self.atomic_swap_mode_event(enabled);
+    }
+
+    /// Enables the atomic swap mode only for the given token. Setting it for an output token
+    /// (WEGLD, USDC or an allowed output) applies to every token swapped into it.
+    #[endpoint(setTokenAtomicSwapMode)]
+    fn set_token_atomic_swap_mode(&self, token_id: TokenIdentifier, enabled: bool) {
+        self.require_caller_has_owner_or_admin_permissions();
+        require!(token_id.is_valid_esdt_identifier(), "Invalid token ID");
+
+        self.token_atomic_swap_mode(&token_id).set(enabled);
+        self.token_atomic_swap_mode_event(&token_id, enabled);
+    }
+
+    /// Allows users to receive output_token from swaps. The route starts from the wrapped token
+    /// and its last hop must return output_token.
+    #[endpoint(addOutputToken)]
+    fn add_output_token(&self, output_token: TokenIdentifier, route: Route<Self::Api>) {
+        self.require_caller_has_owner_or_admin_permissions();
+        require!(output_token.is_valid_esdt_identifier(), "Invalid token ID");
+
+        let route = self.resolve_route(&self.wrapped_token().get(), route, &output_token);
+        self.output_tokens().insert(output_token.clone());
+        self.output_token_route(&output_token).set(&route);
+        self.add_output_token_event(&output_token, &route);
+    }
+
+    /// Adds tokens that dust can be swapped into on the way to WEGLD, such as USDC. The route of a base token
+    /// to WEGLD is its own route in the WEGLD registry, so it must be registered there first.
+    #[endpoint(addBaseTokens)]
+    fn add_base_tokens(&self, tokens: MultiValueEncoded<TokenIdentifier>) {
+        self.require_caller_has_owner_or_admin_permissions();
+
+        let wrapped_token = self.wrapped_token().get();
+        for token in tokens {
+            require!(token != wrapped_token, "Wrapped token is not a base token");
+            require!(self.has_route_to_wrapped_token(&token), "Base token has no route to wrapped token");
+
+            if self.base_tokens().insert(token.clone()) {
+                self.add_base_token_event(&token);
+            }
+        }
+    }
+
+    #[endpoint(removeBaseTokens)]
+    fn remove_base_tokens(&self, tokens: MultiValueEncoded<TokenIdentifier>) {
+        self.require_caller_has_owner_or_admin_permissions();
+
+        for token in tokens {
+            let removed = self.base_tokens().swap_remove(&token);
+            require!(removed, "Base token not found");
+            self.reset_sell_cursor();
+            self.remove_base_token_event(&token);
+        }
+    }
+
+    #[endpoint(removeOutputToken)]
+    fn remove_output_token(&self, output_token: TokenIdentifier) {
+        self.require_caller_has_owner_or_admin_permissions();
+        require!(output_token != self.wrapped_token().get(), "Cannot remove wrapped token");
+
+        let removed = self.output_tokens().swap_remove(&output_token);
+        require!(removed, "Output token not found");
+        self.output_token_route(&output_token).clear();
+        self.remove_output_token_event(&output_token);
+    }
+
+    #[endpoint(addKeepers)]
+    fn add_keepers(&self, address_list: MultiValueEncoded<ManagedAddress>) {
+        self.require_caller_has_owner_or_admin_permissions();
+
+        self.add_permissions_for_all(address_list, Permissions::KEEPER);
+    }
+
+    #[endpoint(removeKeepers)]
+    fn remove_keepers(&self, address_list: MultiValueEncoded<ManagedAddress>) {
+        self.require_caller_has_owner_or_admin_permissions();
+
+        for address in address_list {
+            self.remove_permissions(address, Permissions::KEEPER);
+        }
+    }
+
+    /// When enabled, only keepers can call sellDustTokens. Otherwise anyone can.
+    #[endpoint(setKeeperOnlySelling)]
+    fn set_keeper_only_selling(&self, enabled: bool) {
+        self.require_caller_has_owner_or_admin_permissions();
+
+        self.keeper_only_selling().set(enabled);
+        self.keeper_only_selling_event(enabled);
+    }
+
+    /// Sets the share of the sell proceeds paid to the caller of sellDustTokens.
+    #[endpoint(setKeeperBountyPercentage)]
+    fn set_keeper_bounty_percentage(&self, bounty_percent: u64) {
+        self.require_caller_has_owner_permissions();
+        require!(bounty_percent < MAX_FEE_PERCENTAGE, "Bounty percent invalid");
+
+        self.keeper_bounty_percent().set(bounty_percent);
+        self.keeper_bounty_percentage_event(bounty_percent);
+    }
+
+    /// Sets the most a single sold token can pay as bounty, in the token its proceeds are in.
+    /// No bounty is paid in tokens without a maximum.
+    #[endpoint(setMaxKeeperBounty)]
+    fn set_max_keeper_bounty(&self, token_id: TokenIdentifier, max_bounty: BigUint) {
+        self.require_caller_has_owner_permissions();
+        require!(token_id.is_valid_esdt_identifier(), "Invalid token ID");
+
+        self.max_keeper_bounty_event(&token_id, &max_bounty);
+        self.max_keeper_bounty(&token_id).set(max_bounty);
+    }
+
+    /// Sets the gas sellDustTokensBatch keeps for paying the bounties and forwarding the base token proceeds.
+    /// It stops before selling a token with less gas left.
+    #[endpoint(setSellMinGasLeft)]
+    fn set_sell_min_gas_left(&self, min_gas_left: u64) {
+        self.require_caller_has_owner_or_admin_permissions();
+
+        self.sell_min_gas_left().set(min_gas_left);
+        self.sell_min_gas_left_event(min_gas_left);
+    }
+
+    /// When enabled, the base tokens other than WEGLD received by sellDustTokens, such as USDC, are sold
+    /// for WEGLD through their own route. Otherwise they are kept in the reserve.
+    #[endpoint(setForwardBaseProceeds)]
+    fn set_forward_base_proceeds(&self, enabled: bool) {
+        self.require_caller_has_owner_or_admin_permissions();
+
+        self.forward_base_proceeds().set(enabled);
+        self.forward_base_proceeds_event(enabled);
+    }
+
+    /// When enabled, a swap where no payment is accepted refunds every payment instead of failing.
+    /// The call still fails if it requires a minimum output.
+    #[endpoint(setRefundOnlyAllowed)]
+    fn set_refund_only_allowed(&self, allowed: bool) {
+        self.require_caller_has_owner_or_admin_permissions();
+
+        self.refund_only_allowed().set(allowed);
+        self.refund_only_allowed_event(allowed);
     }
 
+    /// Sets how far the spot price of token_id may be from its safe price, in MAX_PERCENTAGE units.
+    /// Payments above the limit are refunded. A zero limit disables the check.
+    #[endpoint(setMaxPriceDeviation)]
+    fn set_max_price_deviation(&self, token_id: TokenIdentifier, max_deviation: u64) {
+        self.require_caller_has_owner_or_admin_permissions();
+        require!(token_id.is_valid_esdt_identifier(), "Invalid token ID");
+        require!(max_deviation <= MAX_PERCENTAGE, "Deviation percent invalid");
+
+        self.max_price_deviation(&token_id).set(max_deviation);
+        self.max_price_deviation_event(&token_id, max_deviation);
+    }
+
+    /// Sets the lowest value a payment of token_id is accepted for by swaps, in the token's output.
+    /// Payments quoting below it are refunded. Selling is bound by the token's threshold instead.
+    #[endpoint(setMinQuote)]
+    fn set_min_quote(&self, token_id: TokenIdentifier, min_quote: BigUint) {
+        self.require_caller_has_owner_or_admin_permissions();
+        require!(token_id.is_valid_esdt_identifier(), "Invalid token ID");
+
+        self.min_quote(&token_id).set(&min_quote);
+        self.min_quote_event(&token_id, &min_quote);
+    }
+
+    /// Limits the value of token_id accepted by swaps, in the token's output. max_swap_value applies to
+    /// a single payment and epoch_inflow_cap to all the payments of an epoch. A zero value disables the limit.
+    /// Base tokens cannot be capped, as they cannot be refunded once part of the WEGLD leg.
+    #[endpoint(setTokenCaps)]
+    fn set_token_caps(&self, token_id: TokenIdentifier, max_swap_value: BigUint, epoch_inflow_cap: BigUint) {
+        self.require_caller_has_owner_or_admin_permissions();
+        require!(!self.base_tokens().contains(&token_id), "Base tokens cannot be capped");
+
+        let mut config = self.get_token_config(&token_id);
+        config.max_swap_value = max_swap_value;
+        config.epoch_inflow_cap = epoch_inflow_cap;
+        self.set_token_config(&token_id, &config);
+    }
+
+    /// Registers tokens that can be swapped into output_token. Each entry holds the token,
+    /// its route and the minimum value for selling. A route is the ordered list of pairs
+    /// the token goes through, each hop giving the token that the next pair expects.
+    /// The last hop must return output_token. Hops given with the zero address are looked up in the router.
     #[endpoint(addKnownTokens)]
     fn add_known_tokens(&self, output_token: TokenIdentifier, known_tokens: MultiValueEncoded<AddKnownTokenType<Self::Api>>) {
         self.require_caller_has_owner_or_admin_permissions();
 
-        let mut all_tokens_vec = self.all_tokens(&output_token).get();
-        let known_tokens_mapper = self.known_tokens();
+        let mut registry = self.registry_tokens(&output_token);
         for entry in known_tokens {
-            let (token, sc_address, min_amount) = entry.into_tuple();
+            let (token, route, threshold) = entry.into_tuple();
             require!(token.is_valid_esdt_identifier(), "Invalid token ID");
+            require!(self.token_config(&token).is_empty(), "Token already registered");
+            let route = self.resolve_route(&token, route, &output_token);
 
-            if !known_tokens_mapper.contains(&token) {
-                require!(
-                    self.blockchain().is_smart_contract(&sc_address),
-                    "Invalid SC address"
-                );
+            registry.insert(token.clone());
+            self.token_config(&token).set(TokenConfig {
+                enabled: true,
+                output_token: output_token.clone(),
+                route: route.clone(),
+                threshold,
+                max_swap_value: BigUint::zero(),
+                epoch_inflow_cap: BigUint::zero(),
+            });
+            self.add_known_token_event(&output_token, &token, &route);
+        }
+        self.add_usdc_base_token_if_routed();
+    }
 
-                known_tokens_mapper.add(&token);
-                all_tokens_vec.push(token.clone());
-                self.pair_contract(&token).set(PairContractData {
-                    address: sc_address,
+    /// Replaces the route of a registered token. The new route must return the same output token.
+    #[endpoint(updateTokenRoute)]
+    fn update_token_route(&self, token_id: TokenIdentifier, route: Route<Self::Api>) {
+        self.require_caller_has_owner_or_admin_permissions();
+
+        let mut config = self.get_token_config(&token_id);
+        config.route = self.resolve_route(&token_id, route, &config.output_token);
+        self.set_token_config(&token_id, &config);
+    }
+
+    #[endpoint(updateTokenThreshold)]
+    fn update_token_threshold(&self, token_id: TokenIdentifier, threshold: BigUint) {
+        self.require_caller_has_owner_or_admin_permissions();
+
+        let mut config = self.get_token_config(&token_id);
+        config.threshold = threshold;
+        self.set_token_config(&token_id, &config);
+    }
+
+    #[endpoint(setTokenEnabled)]
+    fn set_token_enabled(&self, token_id: TokenIdentifier, enabled: bool) {
+        self.require_caller_has_owner_or_admin_permissions();
+        require!(!self.base_tokens().contains(&token_id), "Cannot change the state of a base token");
+
+        let mut config = self.get_token_config(&token_id);
+        config.enabled = enabled;
+        self.set_token_config(&token_id, &config);
+    }
+
+    /// Moves the tokens of output_token from the storage layout that predates TokenConfig into the registry.
+    /// Migrated tokens are routed through their pair contract and have no caps. Tokens that are no longer
+    /// known, or already registered, are dropped from the old layout. USDC becomes a base token again once
+    /// its route to WEGLD is migrated.
+    #[endpoint(migrateTokenRegistry)]
+    fn migrate_token_registry(&self, output_token: TokenIdentifier) {
+        self.require_caller_has_owner_permissions();
+
+        let mut registry = self.registry_tokens(&output_token);
+        let mut migrated_tokens = ManagedVec::new();
+        for token in self.legacy_all_tokens(&output_token).get().iter() {
+            if self.legacy_known_tokens().contains(&token) && self.token_config(&token).is_empty() {
+                let mut route = ManagedVec::new();
+                route.push(self.legacy_pair_contract(&token).get());
+
+                registry.insert((*token).clone());
+                self.token_config(&token).set(TokenConfig {
+                    enabled: true,
                     output_token: output_token.clone(),
+                    route: route.clone(),
+                    threshold: self.legacy_token_threshold(&token).get(),
+                    max_swap_value: BigUint::zero(),
+                    epoch_inflow_cap: BigUint::zero(),
                 });
-                self.token_threshold(&token).set(min_amount);
+                self.add_known_token_event(&output_token, &token, &route);
+                migrated_tokens.push((*token).clone());
             }
+
+            self.legacy_known_tokens().remove(&token);
+            self.legacy_pair_contract(&token).clear();
+            self.legacy_token_threshold(&token).clear();
         }
-        self.all_tokens(&output_token).set(all_tokens_vec);
+        self.legacy_all_tokens(&output_token).clear();
+        self.migrate_token_registry_event(&output_token, &migrated_tokens);
+        self.add_usdc_base_token_if_routed();
+    }
+
+    /// Registers other pairs that can replace the first hop of a known token's route, such as pools
+    /// of the same tokens on other DEXes sharing the pair interface. Swaps and sells go through
+    /// whichever of them quotes the highest.
+    #[endpoint(addPairCandidates)]
+    fn add_pair_candidates(&self, token_id: TokenIdentifier, pair_addresses: MultiValueEncoded<ManagedAddress>) {
+        self.require_caller_has_owner_or_admin_permissions();
+        require!(!self.token_config(&token_id).is_empty(), "Token not known");
+
+        let first_hop_output = self.get_token_route(&token_id).get(0).output_token;
+        let mut candidates = self.pair_candidates(&token_id);
+        for address in pair_addresses {
+            self.require_valid_pair(&address, &token_id, &first_hop_output);
+
+            if candidates.insert(address.clone()) {
+                self.add_pair_candidate_event(&token_id, &address);
+            }
+        }
+    }
+
+    #[endpoint(removePairCandidates)]
+    fn remove_pair_candidates(&self, token_id: TokenIdentifier, pair_addresses: MultiValueEncoded<ManagedAddress>) {
+        self.require_caller_has_owner_or_admin_permissions();
+
+        let mut candidates = self.pair_candidates(&token_id);
+        for address in pair_addresses {
+            let removed = candidates.swap_remove(&address);
+            require!(removed, "Pair candidate not found");
+            self.remove_pair_candidate_event(&token_id, &address);
+        }
+    }
+
+    /// Allows swapDustTokensAndCall to send its output to the endpoint of the target contract.
+    #[endpoint(addCallTarget)]
+    fn add_call_target(&self, target: ManagedAddress, endpoint_name: ManagedBuffer) {
+        self.require_caller_has_owner_or_admin_permissions();
+        require!(self.blockchain().is_smart_contract(&target), "Invalid SC address");
+        require!(!endpoint_name.is_empty(), "Invalid endpoint name");
+
+        let added = self.call_target_endpoints(&target).insert(endpoint_name.clone());
+        require!(added, "Call target already allowed");
+        self.add_call_target_event(&target, &endpoint_name);
+    }
+
+    #[endpoint(removeCallTarget)]
+    fn remove_call_target(&self, target: ManagedAddress, endpoint_name: ManagedBuffer) {
+        self.require_caller_has_owner_or_admin_permissions();
+
+        let removed = self.call_target_endpoints(&target).swap_remove(&endpoint_name);
+        require!(removed, "Call target not found");
+        self.remove_call_target_event(&target, &endpoint_name);
     }
 
     #[endpoint(removeKnownTokens)]
     fn remove_known_tokens(&self, output_token: TokenIdentifier, tokens: MultiValueEncoded<TokenIdentifier>) {
         self.require_caller_has_owner_or_admin_permissions();
 
-        let mut all_tokens_vec = self.all_tokens(&output_token).get();
-        let known_tokens_mapper = self.known_tokens();
         for token in tokens {
-            if known_tokens_mapper.contains(&token) {
-                known_tokens_mapper.remove(&token);
+            self.remove_registry_token(&output_token, &token);
+        }
+    }
+
+    fn remove_registry_token(&self, output_token: &TokenIdentifier, token_id: &TokenIdentifier) {
+        let config = self.get_token_config(token_id);
+        require!(&config.output_token == output_token, "Token registered under another output token");
+
+        let removed = self.registry_tokens(output_token).swap_remove(token_id);
+        require!(removed, "Token not in registry");
+        self.reset_sell_cursor();
+
+        // A base token without a route to WEGLD can no longer be swapped, so it stops being a base token.
+        if output_token == &self.wrapped_token().get() && self.base_tokens().swap_remove(token_id) {
+            self.remove_base_token_event(token_id);
+        }
+
+        self.token_config(token_id).clear();
+        self.pair_candidates(token_id).clear();
+        self.remove_known_token_event(output_token, token_id);
+    }
+
+    /// Sends the payments to the given address. If unwrap_egld is set, WEGLD is unwrapped and sent as EGLD.
+    fn send_payments(&self, to: &ManagedAddress, payments: &ManagedVec<EsdtTokenPayment>, unwrap_egld: bool) {
+        let wrapped_token = self.wrapped_token().get();
+        let mut esdt_payments = ManagedVec::new();
+        for payment in payments.iter() {
+            if unwrap_egld && payment.token_identifier == wrapped_token {
+                self.unwrap_and_send_egld(to, payment.amount);
+            } else {
+                esdt_payments.push(payment);
+            }
+        }
+
+        if !esdt_payments.is_empty() {
+            self.send().direct_multi(to, &esdt_payments);
+        }
+    }
+
+    fn unwrap_and_send_egld(&self, to: &ManagedAddress, amount: BigUint) {
+        require!(!self.wrapping_contract().is_empty(), "Wrapping contract not set");
+
+        self.unwrap_egld(self.wrapping_contract().get(), self.wrapped_token().get(), amount.clone());
+        self.send().direct_egld(to, &amount);
+    }
+
+    /// Checks a route starting from token_in and returns it with the pairs of the hops given with
+    /// the zero address looked up in the router.
+    fn resolve_route(&self, token_in: &TokenIdentifier, route: Route<Self::Api>, output_token: &TokenIdentifier) -> Route<Self::Api> {
+        require!(!route.is_empty(), "Empty route");
+
+        let last_hop = route.get(route.len() - 1);
+        require!(&last_hop.output_token == output_token, "Route does not end in output token");
+
+        let mut resolved_route = ManagedVec::new();
+        let mut token = token_in.clone();
+        for mut hop in route.iter() {
+            if hop.address.is_zero() {
+                hop.address = self.find_router_pair(&token, &hop.output_token);
+            }
+
+            self.require_valid_pair(&hop.address, &token, &hop.output_token);
+            token = hop.output_token.clone();
+            resolved_route.push(hop);
+        }
+
+        resolved_route
+    }
+
+    fn find_router_pair(&self, first_token_id: &TokenIdentifier, second_token_id: &TokenIdentifier) -> ManagedAddress {
+        require!(!self.router_address().is_empty(), "Router not set");
+
+        let pair_address = self.get_router_pair(self.router_address().get(), first_token_id.clone(), second_token_id.clone());
+        require!(!pair_address.is_zero(), "Pair not found");
+
+        pair_address
+    }
+
+    /// Requires the pair to be an active pair of token_in and token_out, in either order.
+    fn require_valid_pair(&self, pair_address: &ManagedAddress, token_in: &TokenIdentifier, token_out: &TokenIdentifier) {
+        require!(
+            self.blockchain().is_smart_contract(pair_address),
+            "Invalid SC address"
+        );
+
+        let (first_token_id, second_token_id) = self.get_pair_tokens(pair_address.clone());
+        let trades_tokens = (&first_token_id == token_in && &second_token_id == token_out)
+            || (&first_token_id == token_out && &second_token_id == token_in);
+        require!(trades_tokens, "Pair does not trade route tokens");
+        require!(self.get_pair_state(pair_address.clone()) == PairState::Active, "Pair not active");
+    }
+
+    #[inline]
+    fn is_atomic_swap(&self, token_id: &TokenIdentifier) -> bool {
+        self.atomic_swap_mode().get() || self.token_atomic_swap_mode(token_id).get()
+    }
+
+    /// Whether the spot value of a token is further from its safe value than the token's limit allows.
+    fn is_price_deviation_exceeded(&self, token_id: &TokenIdentifier, spot_amount: &BigUint, safe_amount: &BigUint) -> bool {
+        let max_deviation = self.max_price_deviation(token_id).get();
+        if max_deviation == 0 {
+            return false;
+        }
+
+        if safe_amount == &BigUint::zero() {
+            return true;
+        }
+
+        let deviation = if spot_amount > safe_amount {
+            spot_amount - safe_amount
+        } else {
+            safe_amount - spot_amount
+        };
+        deviation * MAX_PERCENTAGE / safe_amount > max_deviation
+    }
+
+    fn get_keeper_bounty(&self, proceeds: &EsdtTokenPayment) -> BigUint {
+        let bounty = &proceeds.amount * self.keeper_bounty_percent().get() / MAX_PERCENTAGE;
+        min_amount(bounty, self.max_keeper_bounty(&proceeds.token_identifier).get())
+    }
+
+    /// sellDustTokensBatch walks the registries by index and removing an entry moves the last one in its place,
+    /// so the batch starts over instead of skipping the moved token.
+    fn reset_sell_cursor(&self) {
+        self.sell_cursor().clear();
+    }
+
+    fn get_sell_min_gas_left(&self) -> u64 {
+        if self.sell_min_gas_left().is_empty() {
+            return DEFAULT_SELL_MIN_GAS_LEFT;
+        }
+
+        self.sell_min_gas_left().get()
+    }
+
+    /// The output tokens whose known tokens are walked by sellDustTokensBatch, in order: WEGLD, then the base tokens.
+    fn get_registry_outputs(&self) -> ManagedVec<TokenIdentifier> {
+        let mut outputs = ManagedVec::new();
+        outputs.push(self.wrapped_token().get());
+        for base_token in self.base_tokens().iter() {
+            outputs.push(base_token);
+        }
+
+        outputs
+    }
+
+    /// The most value of token_id a payment can be accepted for, or None if the token has no caps.
+    fn get_allowed_swap_value(&self, token_id: &TokenIdentifier) -> Option<BigUint> {
+        let config = self.get_token_config(token_id);
+        let mut allowed_value = if config.max_swap_value > 0 { Some(config.max_swap_value) } else { None };
+
+        if config.epoch_inflow_cap > 0 {
+            let remaining_inflow = self.get_remaining_epoch_inflow(token_id);
+            allowed_value = match allowed_value {
+                Some(value) if value < remaining_inflow => Some(value),
+                _ => Some(remaining_inflow),
+            };
+        }
+
+        allowed_value
+    }
+
+    fn get_epoch_inflow(&self, token_id: &TokenIdentifier) -> BigUint {
+        if self.epoch_inflow(token_id).is_empty() {
+            return BigUint::zero();
+        }
+
+        let inflow = self.epoch_inflow(token_id).get();
+        if inflow.epoch != self.blockchain().get_block_epoch() {
+            return BigUint::zero();
+        }
+
+        inflow.amount
+    }
+
+    fn get_remaining_epoch_inflow(&self, token_id: &TokenIdentifier) -> BigUint {
+        let inflow_cap = self.get_token_config(token_id).epoch_inflow_cap;
+        let inflow = self.get_epoch_inflow(token_id);
+        if inflow >= inflow_cap {
+            return BigUint::zero();
+        }
+
+        inflow_cap - inflow
+    }
+
+    fn add_epoch_inflow(&self, token_id: &TokenIdentifier, value: &BigUint) {
+        if self.get_token_config(token_id).epoch_inflow_cap == 0 {
+            return;
+        }
+
+        let amount = self.get_epoch_inflow(token_id) + value;
+        self.epoch_inflow(token_id).set(EpochInflow {
+            epoch: self.blockchain().get_block_epoch(),
+            amount,
+        });
+    }
+
+    /// Returns the maximum value per swap, the epoch inflow cap and what is left of it in the current epoch.
+    /// Zero caps are disabled.
+    #[view(getTokenCapStatus)]
+    fn get_token_cap_status(&self, token_id: TokenIdentifier) -> MultiValue3<BigUint, BigUint, BigUint> {
+        let config = self.get_token_config(&token_id);
+        (
+            config.max_swap_value,
+            config.epoch_inflow_cap,
+            self.get_remaining_epoch_inflow(&token_id),
+        ).into()
+    }
+
+    fn get_token_config(&self, token_id: &TokenIdentifier) -> TokenConfig<Self::Api> {
+        require!(!self.token_config(token_id).is_empty(), "Token not registered");
+
+        self.token_config(token_id).get()
+    }
+
+    fn set_token_config(&self, token_id: &TokenIdentifier, config: &TokenConfig<Self::Api>) {
+        self.token_config(token_id).set(config);
+        self.update_token_config_event(token_id, config);
+    }
+
+    /// Whether token_id is registered and enabled.
+    fn is_known_token(&self, token_id: &TokenIdentifier) -> bool {
+        !self.token_config(token_id).is_empty() && self.token_config(token_id).get().enabled
+    }
+
+    /// Whether token_id is known, in the WEGLD registry and routed to WEGLD, as required of a base token.
+    fn has_route_to_wrapped_token(&self, token_id: &TokenIdentifier) -> bool {
+        if !self.is_known_token(token_id) {
+            return false;
+        }
 
-                unsafe {
-                    let index = all_tokens_vec.find(&token).unwrap_unchecked();
-                    all_tokens_vec.remove(index);
-                }
+        let wrapped_token = self.wrapped_token().get();
+        let route = self.token_config(token_id).get().route;
+        self.registry_tokens(&wrapped_token).contains(token_id) && route.get(route.len() - 1).output_token == wrapped_token
+    }
 
-                self.pair_contract(&token).clear();
-                self.token_threshold(&token).clear();
+    /// Makes the USDC token given at init a base token once it has a route to WEGLD.
+    fn add_usdc_base_token_if_routed(&self) {
+        let usdc_token = self.usdc_token().get();
+        if self.has_route_to_wrapped_token(&usdc_token) && self.base_tokens().insert(usdc_token.clone()) {
+            self.add_base_token_event(&usdc_token);
+        }
+    }
+
+    /// Returns the full route of a registered token, starting with its pair contract. Empty if the token is not registered.
+    #[view(getTokenRoute)]
+    fn get_token_route(&self, token_id: &TokenIdentifier) -> Route<Self::Api> {
+        if self.token_config(token_id).is_empty() {
+            return ManagedVec::new();
+        }
+
+        self.token_config(token_id).get().route
+    }
+
+    #[view(getTokenThreshold)]
+    fn get_token_threshold(&self, token_id: &TokenIdentifier) -> BigUint {
+        if self.token_config(token_id).is_empty() {
+            return BigUint::zero();
+        }
+
+        self.token_config(token_id).get().threshold
+    }
+
+    /// Returns the route of a known token that quotes amount_in the highest, trying its pair contract
+    /// and every pair candidate as the first hop. The spot and safe quotes of the route are returned with it.
+    /// Pairs that are not active or have no reserves are not quoted, None is returned if no route is left.
+    fn get_best_token_route(&self, token_id: &TokenIdentifier, amount_in: &BigUint) -> Option<(Route<Self::Api>, BigUint, BigUint)> {
+        let token_route = self.get_token_route(token_id);
+        let first_hop_output = token_route.get(0).output_token;
+        let next_hops = token_route.slice(1, token_route.len()).unwrap_or_else(ManagedVec::new);
+
+        let mut best = None;
+        let mut best_amount = BigUint::zero();
+        if let Some((spot, safe)) = self.try_get_spot_and_safe_amount_out_through_route(&token_route, token_id.clone(), amount_in.clone()) {
+            best_amount = min_amount(spot.clone(), safe.clone());
+            best = Some((token_route, spot, safe));
+        }
+
+        for address in self.pair_candidates(token_id).iter() {
+            let mut route = ManagedVec::new();
+            route.push(PairContractData {
+                address,
+                output_token: first_hop_output.clone(),
+            });
+            route.extend(&next_hops);
+
+            let (spot, safe) = match self.try_get_spot_and_safe_amount_out_through_route(&route, token_id.clone(), amount_in.clone()) {
+                Some(amounts) => amounts,
+                None => continue,
+            };
+            let amount = min_amount(spot.clone(), safe.clone());
+            if best.is_none() || amount > best_amount {
+                best_amount = amount;
+                best = Some((route, spot, safe));
             }
         }
-        self.all_tokens(&output_token).set(&all_tokens_vec);
+
+        best
     }
 
     #[view(getAllTokens)]
     fn get_all_tokens(&self, output_token: TokenIdentifier) -> MultiValueEncoded<TokenIdentifier> {
-        self.all_tokens(&output_token).get().into()
+        self.registry_tokens(&output_token).iter().collect()
+    }
+
+    /// Returns up to size registered tokens of output_token with their configuration, skipping the first from.
+    #[view(getTokenConfigs)]
+    fn get_token_configs(
+        &self,
+        output_token: TokenIdentifier,
+        from: usize,
+        size: usize
+    ) -> MultiValueEncoded<MultiValue2<TokenIdentifier, TokenConfig<Self::Api>>> {
+        let registry = self.registry_tokens(&output_token);
+        let end = core::cmp::min(from.saturating_add(size), registry.len());
+        let mut result = MultiValueEncoded::new();
+        for index in from..end {
+            let token = registry.get_by_index(index + 1);
+            let config = self.token_config(&token).get();
+            result.push((token, config).into());
+        }
+
+        result
+    }
+
+    #[view(getTokenCount)]
+    fn get_token_count(&self, output_token: TokenIdentifier) -> usize {
+        self.registry_tokens(&output_token).len()
     }
 
+    #[view(getTokenConfig)]
+    #[storage_mapper("token_config")]
+    fn token_config(&self, token_id: &TokenIdentifier) -> SingleValueMapper<TokenConfig<Self::Api>>;
+
+    /// The registered tokens of each output token.
+    #[storage_mapper("registry_tokens")]
+    fn registry_tokens(&self, output_token: &TokenIdentifier) -> UnorderedSetMapper<TokenIdentifier>;
+
+    /// Endpoints of the target contract that swapDustTokensAndCall can send its output to.
+    #[view(getCallTargetEndpoints)]
+    #[storage_mapper("call_target_endpoints")]
+    fn call_target_endpoints(&self, target: &ManagedAddress) -> UnorderedSetMapper<ManagedBuffer>;
+
+    /// Pairs that can replace the first hop of a token's route, returning the same token.
+    #[view(getPairCandidates)]
+    #[storage_mapper("pair_candidates")]
+    fn pair_candidates(&self, token_id: &TokenIdentifier) -> UnorderedSetMapper<ManagedAddress>;
+
+    // Token registry layout prior to TokenConfig, only read by migrateTokenRegistry.
+
     #[storage_mapper("pair_contract")]
-    fn pair_contract(&self, token_id: &TokenIdentifier) -> SingleValueMapper<PairContractData<Self::Api>>;
+    fn legacy_pair_contract(&self, token_id: &TokenIdentifier) -> SingleValueMapper<PairContractData<Self::Api>>;
 
-    #[view(getTokenThreshold)]
     #[storage_mapper("token_threshold")]
-    fn token_threshold(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+    fn legacy_token_threshold(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
 
     #[storage_mapper("known_tokens")]
-    fn known_tokens(&self) -> WhitelistMapper<Self::Api, TokenIdentifier>;
+    fn legacy_known_tokens(&self) -> WhitelistMapper<Self::Api, TokenIdentifier>;
 
     #[storage_mapper("all_tokens")]
-    fn all_tokens(&self, output_token: &TokenIdentifier) -> SingleValueMapper<ManagedVec<TokenIdentifier>>;
+    fn legacy_all_tokens(&self, output_token: &TokenIdentifier) -> SingleValueMapper<ManagedVec<TokenIdentifier>>;
 
     #[view(getProtocolFeePercent)]
     #[storage_mapper("protocol_fee_percent")]
@@ -138,6 +835,76 @@ pub trait ConfigModule:
     #[storage_mapper("usdc_token_id")]
     fn usdc_token(&self) -> SingleValueMapper<TokenIdentifier>;
 
-    #[storage_mapper("collected_fee_amount")]
-    fn collected_fee_amount(&self) -> SingleValueMapper<BigUint>;
+    /// Tokens, besides WEGLD, that swaps go through on the way to WEGLD.
+    #[view(getBaseTokens)]
+    #[storage_mapper("base_tokens")]
+    fn base_tokens(&self) -> UnorderedSetMapper<TokenIdentifier>;
+
+    #[view(getAtomicSwapMode)]
+    #[storage_mapper("atomic_swap_mode")]
+    fn atomic_swap_mode(&self) -> SingleValueMapper<bool>;
+
+    #[view(getTokenAtomicSwapMode)]
+    #[storage_mapper("token_atomic_swap_mode")]
+    fn token_atomic_swap_mode(&self, token_id: &TokenIdentifier) -> SingleValueMapper<bool>;
+
+    #[view(getMaxPriceDeviation)]
+    #[storage_mapper("max_price_deviation")]
+    fn max_price_deviation(&self, token_id: &TokenIdentifier) -> SingleValueMapper<u64>;
+
+    #[view(getMinQuote)]
+    #[storage_mapper("min_quote")]
+    fn min_quote(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    #[storage_mapper("epoch_inflow")]
+    fn epoch_inflow(&self, token_id: &TokenIdentifier) -> SingleValueMapper<EpochInflow<Self::Api>>;
+
+    #[view(getKeeperOnlySelling)]
+    #[storage_mapper("keeper_only_selling")]
+    fn keeper_only_selling(&self) -> SingleValueMapper<bool>;
+
+    #[view(getKeeperBountyPercent)]
+    #[storage_mapper("keeper_bounty_percent")]
+    fn keeper_bounty_percent(&self) -> SingleValueMapper<u64>;
+
+    #[view(getMaxKeeperBounty)]
+    #[storage_mapper("max_keeper_bounty")]
+    fn max_keeper_bounty(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    #[view(getSellMinGasLeft)]
+    #[storage_mapper("sell_min_gas_left")]
+    fn sell_min_gas_left(&self) -> SingleValueMapper<u64>;
+
+    #[view(getSellCursor)]
+    #[storage_mapper("sell_cursor")]
+    fn sell_cursor(&self) -> SingleValueMapper<SellCursor>;
+
+    #[view(getForwardBaseProceeds)]
+    #[storage_mapper("forward_base_proceeds")]
+    fn forward_base_proceeds(&self) -> SingleValueMapper<bool>;
+
+    #[view(getRefundOnlyAllowed)]
+    #[storage_mapper("refund_only_allowed")]
+    fn refund_only_allowed(&self) -> SingleValueMapper<bool>;
+
+    #[view(getRouterAddress)]
+    #[storage_mapper("router_address")]
+    fn router_address(&self) -> SingleValueMapper<ManagedAddress>;
+
+    #[view(getWrappingContract)]
+    #[storage_mapper("wrapping_contract")]
+    fn wrapping_contract(&self) -> SingleValueMapper<ManagedAddress>;
+
+    #[view(getOutputTokens)]
+    #[storage_mapper("output_tokens")]
+    fn output_tokens(&self) -> UnorderedSetMapper<TokenIdentifier>;
+
+    #[view(getOutputTokenRoute)]
+    #[storage_mapper("output_token_route")]
+    fn output_token_route(&self, output_token: &TokenIdentifier) -> SingleValueMapper<Route<Self::Api>>;
+
+    /// Every token that fees were ever collected in.
+    #[storage_mapper("fee_tokens")]
+    fn fee_tokens(&self) -> UnorderedSetMapper<TokenIdentifier>;
+
 }
@@ -0,0 +1,185 @@
+use crate::config::{Route, TokenConfig};
+use crate::referral::TierDetails;
+use crate::registry::RegistryIssue;
+use crate::sell::ExecutionPolicy;
+
+elrond_wasm::imports!();
+elrond_wasm::derive_imports!();
+
+#[derive(TypeAbi, TopEncode)]
+pub struct SwapDustTokensEvent<M: ManagedTypeApi> {
+    pub payments: ManagedVec<M, EsdtTokenPayment<M>>,
+    pub output_token: EgldOrEsdtTokenIdentifier<M>,
+    pub amount_out: BigUint<M>,
+    pub protocol_fee: BigUint<M>,
+    pub referral_tag: Option<ManagedBuffer<M>>,
+    pub referral_fee: BigUint<M>,
+    pub refunds: ManagedVec<M, EsdtTokenPayment<M>>,
+}
+
+#[derive(TypeAbi, TopEncode)]
+pub struct SellDustTokenEvent<M: ManagedTypeApi> {
+    pub amount_in: BigUint<M>,
+    pub proceeds: EsdtTokenPayment<M>,
+    pub keeper_bounty: BigUint<M>,
+}
+
+#[elrond_wasm::module]
+pub trait EventsModule {
+
+    #[event("swapDustTokens")]
+    fn swap_dust_tokens_event(
+        &self,
+        #[indexed] caller: &ManagedAddress,
+        swap: &SwapDustTokensEvent<Self::Api>
+    );
+
+    #[event("sellDustToken")]
+    fn sell_dust_token_event(
+        &self,
+        #[indexed] caller: &ManagedAddress,
+        #[indexed] token_id: &TokenIdentifier,
+        sell: &SellDustTokenEvent<Self::Api>
+    );
+
+    #[event("protocolFeePercentage")]
+    fn protocol_fee_percentage_event(&self, fee_percent: u64);
+
+    #[event("slippagePercentage")]
+    fn slippage_percentage_event(&self, slippage_percent: u64);
+
+    #[event("wrappingContract")]
+    fn wrapping_contract_event(&self, #[indexed] address: &ManagedAddress);
+
+    #[event("routerAddress")]
+    fn router_address_event(&self, #[indexed] address: &ManagedAddress);
+
+    #[event("atomicSwapMode")]
+    fn atomic_swap_mode_event(&self, enabled: bool);
+
+    #[event("tokenAtomicSwapMode")]
+    fn token_atomic_swap_mode_event(&self, #[indexed] token_id: &TokenIdentifier, enabled: bool);
+
+    #[event("maxPriceDeviation")]
+    fn max_price_deviation_event(&self, #[indexed] token_id: &TokenIdentifier, max_deviation: u64);
+
+    #[event("refundOnlyAllowed")]
+    fn refund_only_allowed_event(&self, allowed: bool);
+
+    #[event("forwardBaseProceeds")]
+    fn forward_base_proceeds_event(&self, enabled: bool);
+
+    #[event("keeperOnlySelling")]
+    fn keeper_only_selling_event(&self, enabled: bool);
+
+    #[event("keeperBountyPercentage")]
+    fn keeper_bounty_percentage_event(&self, bounty_percent: u64);
+
+    #[event("maxKeeperBounty")]
+    fn max_keeper_bounty_event(&self, #[indexed] token_id: &TokenIdentifier, max_bounty: &BigUint);
+
+    #[event("sellMinGasLeft")]
+    fn sell_min_gas_left_event(&self, min_gas_left: u64);
+
+    #[event("executionPolicy")]
+    fn execution_policy_event(&self, #[indexed] token_id: &TokenIdentifier, policy: &ExecutionPolicy<Self::Api>);
+
+    #[event("removeExecutionPolicy")]
+    fn remove_execution_policy_event(&self, #[indexed] token_id: &TokenIdentifier);
+
+    #[event("sellLossTolerance")]
+    fn sell_loss_tolerance_event(&self, tolerance: u64);
+
+    #[event("keeperBounty")]
+    fn keeper_bounty_event(&self, #[indexed] caller: &ManagedAddress, bounties: &ManagedVec<EsdtTokenPayment>);
+
+    #[event("extractFees")]
+    fn extract_fees_event(&self, #[indexed] caller: &ManagedAddress, fees: &ManagedVec<EsdtTokenPayment>);
+
+    #[event("topUp")]
+    fn top_up_event(&self, #[indexed] caller: &ManagedAddress, #[indexed] token_id: &TokenIdentifier, amount: &BigUint);
+
+    #[event("addKnownToken")]
+    fn add_known_token_event(
+        &self,
+        #[indexed] output_token: &TokenIdentifier,
+        #[indexed] token_id: &TokenIdentifier,
+        route: &Route<Self::Api>
+    );
+
+    #[event("updateTokenConfig")]
+    fn update_token_config_event(&self, #[indexed] token_id: &TokenIdentifier, config: &TokenConfig<Self::Api>);
+
+    #[event("repairTokenRegistry")]
+    fn repair_token_registry_event(&self, #[indexed] caller: &ManagedAddress, issues: &ManagedVec<RegistryIssue<Self::Api>>);
+
+    #[event("migrateTokenRegistry")]
+    fn migrate_token_registry_event(&self, #[indexed] output_token: &TokenIdentifier, tokens: &ManagedVec<TokenIdentifier>);
+
+    #[event("removeKnownToken")]
+    fn remove_known_token_event(&self, #[indexed] output_token: &TokenIdentifier, #[indexed] token_id: &TokenIdentifier);
+
+    #[event("minQuote")]
+    fn min_quote_event(&self, #[indexed] token_id: &TokenIdentifier, min_quote: &BigUint);
+
+    #[event("addPairCandidate")]
+    fn add_pair_candidate_event(&self, #[indexed] token_id: &TokenIdentifier, #[indexed] pair_address: &ManagedAddress);
+
+    #[event("removePairCandidate")]
+    fn remove_pair_candidate_event(&self, #[indexed] token_id: &TokenIdentifier, #[indexed] pair_address: &ManagedAddress);
+
+    #[event("addCallTarget")]
+    fn add_call_target_event(&self, #[indexed] target: &ManagedAddress, #[indexed] endpoint_name: &ManagedBuffer);
+
+    #[event("removeCallTarget")]
+    fn remove_call_target_event(&self, #[indexed] target: &ManagedAddress, #[indexed] endpoint_name: &ManagedBuffer);
+
+    #[event("addOutputToken")]
+    fn add_output_token_event(&self, #[indexed] output_token: &TokenIdentifier, route: &Route<Self::Api>);
+
+    #[event("addBaseToken")]
+    fn add_base_token_event(&self, #[indexed] token_id: &TokenIdentifier);
+
+    #[event("removeBaseToken")]
+    fn remove_base_token_event(&self, #[indexed] token_id: &TokenIdentifier);
+
+    #[event("removeOutputToken")]
+    fn remove_output_token_event(&self, #[indexed] output_token: &TokenIdentifier);
+
+    #[event("addTierDetails")]
+    fn add_tier_details_event(&self, tier: &TierDetails<Self::Api>);
+
+    #[event("removeTierDetails")]
+    fn remove_tier_details_event(&self, #[indexed] name: &ManagedBuffer);
+
+    #[event("referralFeePercentage")]
+    fn referral_fee_percentage_event(&self, #[indexed] tag: &ManagedBuffer, fee_percent: u64);
+
+    #[event("migrateProtocolFees")]
+    fn migrate_protocol_fees_event(&self, #[indexed] token_id: &TokenIdentifier, amount: &BigUint);
+
+    #[event("migrateTagFees")]
+    fn migrate_tag_fees_event(&self, #[indexed] tag: &ManagedBuffer, #[indexed] token_id: &TokenIdentifier, amount: &BigUint);
+
+    #[event("migrateReserve")]
+    fn migrate_reserve_event(&self, #[indexed] token_id: &TokenIdentifier, amount: &BigUint);
+
+    #[event("registerReferralTag")]
+    fn register_referral_tag_event(&self, #[indexed] caller: &ManagedAddress, #[indexed] tag: &ManagedBuffer);
+
+    #[event("removeReferralTag")]
+    fn remove_referral_tag_event(
+        &self,
+        #[indexed] user_address: &ManagedAddress,
+        #[indexed] tag: &ManagedBuffer,
+        fees: &ManagedVec<EsdtTokenPayment>
+    );
+
+    #[event("claimReferralFees")]
+    fn claim_referral_fees_event(
+        &self,
+        #[indexed] caller: &ManagedAddress,
+        #[indexed] tag: &ManagedBuffer,
+        fees: &ManagedVec<EsdtTokenPayment>
+    );
+}
@@ -0,0 +1,118 @@
+use crate::config::{self, MAX_PERCENTAGE};
+use crate::proxy::{self, min_amount};
+use crate::{events, reserve};
+
+elrond_wasm::imports!();
+elrond_wasm::derive_imports!();
+
+/// Quantity of a token held from swaps and what was paid for it, in the token's output.
+#[derive(TopEncode, TopDecode, TypeAbi, Clone, PartialEq, Debug)]
+pub struct Inventory<M: ManagedTypeApi> {
+    pub quantity: BigUint<M>,
+    pub cost: BigUint<M>,
+}
+
+#[elrond_wasm::module]
+pub trait InventoryModule:
+    permissions_module::PermissionsModule
+    + pausable::PausableModule
+    + config::ConfigModule
+    + proxy::ProxyModule
+    + reserve::ReserveModule
+    + events::EventsModule
+{
+
+    /// Refuses selling a token for less than its cost basis minus the tolerance, in MAX_PERCENTAGE units.
+    /// Selling is not loss protected until a tolerance is set.
+    #[endpoint(setSellLossTolerance)]
+    fn set_sell_loss_tolerance(&self, tolerance: u64) {
+        self.require_caller_has_owner_or_admin_permissions();
+        require!(tolerance <= MAX_PERCENTAGE, "Tolerance percent invalid");
+
+        self.sell_loss_tolerance().set(tolerance);
+        self.sell_loss_tolerance_event(tolerance);
+    }
+
+    fn get_inventory(&self, token_id: &TokenIdentifier) -> Inventory<Self::Api> {
+        if self.inventory(token_id).is_empty() {
+            return Inventory {
+                quantity: BigUint::zero(),
+                cost: BigUint::zero(),
+            };
+        }
+
+        self.inventory(token_id).get()
+    }
+
+    fn add_inventory(&self, token_id: &TokenIdentifier, quantity: &BigUint, cost: &BigUint) {
+        let mut inventory = self.get_inventory(token_id);
+        inventory.quantity += quantity;
+        inventory.cost += cost;
+
+        self.inventory(token_id).set(inventory);
+    }
+
+    /// The cost of selling amount of token_id at the inventory's average cost. The part of amount
+    /// over the inventory quantity was never paid for.
+    fn get_cost_basis(&self, token_id: &TokenIdentifier, amount: &BigUint) -> BigUint {
+        let inventory = self.get_inventory(token_id);
+        if inventory.quantity == 0 {
+            return BigUint::zero();
+        }
+
+        let quantity = min_amount(amount.clone(), inventory.quantity.clone());
+        inventory.cost * quantity / inventory.quantity
+    }
+
+    fn is_sell_loss_exceeded(&self, cost_basis: &BigUint, value: &BigUint) -> bool {
+        if self.sell_loss_tolerance().is_empty() {
+            return false;
+        }
+
+        let tolerance = self.sell_loss_tolerance().get();
+        let min_value = cost_basis * (MAX_PERCENTAGE - tolerance) / MAX_PERCENTAGE;
+        value < &min_value
+    }
+
+    /// Removes the sold amount from the inventory and realizes its profit or loss.
+    fn record_sale(&self, token_id: &TokenIdentifier, amount: &BigUint, proceeds: &BigUint) {
+        let cost_basis = self.get_cost_basis(token_id, amount);
+        let mut inventory = self.get_inventory(token_id);
+        inventory.quantity -= min_amount(amount.clone(), inventory.quantity.clone());
+        inventory.cost -= &cost_basis;
+        self.inventory(token_id).set(inventory);
+
+        let pnl = BigInt::from(proceeds.clone()) - BigInt::from(cost_basis);
+        self.realized_pnl(token_id).update(|x| *x += pnl);
+    }
+
+    /// Returns the quantity held, its cost, the realized PnL and the PnL of selling the quantity held
+    /// at the current price. Amounts are in the token's output.
+    #[view(getTokenPnl)]
+    fn get_token_pnl(&self, token_id: TokenIdentifier) -> MultiValue4<BigUint, BigUint, BigInt, BigInt> {
+        let inventory = self.get_inventory(&token_id);
+        let best_route = if inventory.quantity == 0 || !self.is_known_token(&token_id) {
+            None
+        } else {
+            self.get_best_token_route(&token_id, &inventory.quantity)
+        };
+        let value = match best_route {
+            Some((_, spot_value, safe_value)) => min_amount(spot_value, safe_value),
+            None => BigUint::zero(),
+        };
+        let unrealized_pnl = BigInt::from(value) - BigInt::from(inventory.cost.clone());
+
+        (inventory.quantity, inventory.cost, self.realized_pnl(&token_id).get(), unrealized_pnl).into()
+    }
+
+    #[storage_mapper("inventory")]
+    fn inventory(&self, token_id: &TokenIdentifier) -> SingleValueMapper<Inventory<Self::Api>>;
+
+    #[view(getRealizedPnl)]
+    #[storage_mapper("realized_pnl")]
+    fn realized_pnl(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigInt>;
+
+    #[view(getSellLossTolerance)]
+    #[storage_mapper("sell_loss_tolerance")]
+    fn sell_loss_tolerance(&self) -> SingleValueMapper<u64>;
+}
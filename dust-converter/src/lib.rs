@@ -1,12 +1,22 @@
 #![no_std]
 
-use config::{MAX_PERCENTAGE, MAX_FEE_PERCENTAGE};
+use config::{Route, MAX_PERCENTAGE, MAX_FEE_PERCENTAGE};
+use proxy::min_amount;
+use events::{SellDustTokenEvent, SwapDustTokensEvent};
+use quote::{ForwardCall, RefundQuote, RefundReason, SwapLeg, SwapMode, SwapQuote, SwapRecipients, SwapResult, TokenQuote};
+use sell::{SellCursor, SellSummary, SkipReason, SkippedToken, SoldToken};
 
 elrond_wasm::imports!();
 
 pub mod config;
+pub mod events;
+pub mod inventory;
 pub mod proxy;
+pub mod quote;
 pub mod referral;
+pub mod registry;
+pub mod reserve;
+pub mod sell;
 use pausable::State;
 use permissions_module::Permissions;
 
@@ -15,6 +25,11 @@ pub trait DustConverter:
     config::ConfigModule
     + proxy::ProxyModule
     + referral::ReferralModule
+    + reserve::ReserveModule
+    + events::EventsModule
+    + inventory::InventoryModule
+    + sell::SellModule
+    + registry::RegistryModule
     + permissions_module::PermissionsModule
     + pausable::PausableModule
 {
@@ -49,133 +64,824 @@ pub trait DustConverter:
         );
         self.wrapped_token().set_if_empty(wegld_token);
         self.usdc_token().set_if_empty(usdc_token);
-        self.collected_fee_amount().set_if_empty(BigUint::zero());
+        require!(self.usdc_token().get() != self.wrapped_token().get(), "Wrapped token is not a base token");
+        self.output_tokens().insert(self.wrapped_token().get());
+
+        // on a new deploy USDC becomes a base token once its route to WEGLD is registered
+        self.add_usdc_base_token_if_routed();
         self.state().set(State::Inactive);
 
         let all_permissions = Permissions::OWNER | Permissions::ADMIN | Permissions::PAUSE;
         self.set_permissions(self.blockchain().get_caller(), all_permissions);
     }
 
+    /// Quotes the payments in output_token. Returns the total amount, the part of it that was actually received,
+    /// the quote of every payment and the payments to refund. Payments without an available pair on their route
+    /// or quoting below the token's minimum quote are refunded, so the other payments still settle. Depending on mode, payments are sold
+    /// right away and count with the amount actually received. The part of a payment over the token's caps is
+    /// refunded and, unless only quoting, the accepted value counts towards the token's epoch inflow. Payments
+    /// kept unsold are added to the inventory at their value, except for base tokens which are not dust.
     fn compute_swap_amount(
         &self, 
         output_token: TokenIdentifier, 
-        payments: &ManagedVec<EsdtTokenPayment>
-    ) -> (BigUint, ManagedVec<EsdtTokenPayment>) {
-        if payments.is_empty() {
-            return (BigUint::zero(), ManagedVec::new());
-        }
-
-        let known_tokens_mapper = self.known_tokens();
+        payments: &ManagedVec<EsdtTokenPayment>,
+        mode: SwapMode
+    ) -> SwapLeg<Self::Api> {
+        let mut leg = SwapLeg::default();
+        for mut p in payments {
+            // the base tokens of the WEGLD leg are only quoted, they cannot be refunded
+            let is_base_token = self.base_tokens().contains(&p.token_identifier);
+            if !self.is_known_token(&p.token_identifier) {
+                require!(!is_base_token, "Base token not known");
+                leg.refunds.push(RefundQuote { payment: p, reason: RefundReason::UnknownToken });
+                continue;
+            }
 
-        let mut total_amount = BigUint::zero();
-        let mut refund_payments = ManagedVec::new();
-        for p in payments {
-            if !known_tokens_mapper.contains(&p.token_identifier) {
-                refund_payments.push(p);
+            let (route, spot_value, safe_value) = match self.get_best_token_route(&p.token_identifier, &p.amount) {
+                Some(best_route) => best_route,
+                None => {
+                    require!(!is_base_token, "Pair not available");
+                    leg.refunds.push(RefundQuote { payment: p, reason: RefundReason::PairUnavailable });
+                    continue;
+                }
+            };
+            require!(route.get(route.len() - 1).output_token == output_token, "Invalid payments");
+
+            if self.is_price_deviation_exceeded(&p.token_identifier, &spot_value, &safe_value) {
+                require!(!is_base_token, "Price deviation too high");
+                leg.refunds.push(RefundQuote { payment: p, reason: RefundReason::PriceDeviation });
                 continue;
             }
 
-            let pair = self.pair_contract(&p.token_identifier).get();
-            require!(pair.output_token == output_token, "Invalid payments");
+            // token thresholds only apply to selling, swaps are bound by the minimum quote
+            let mut value = min_amount(spot_value, safe_value);
+            if !is_base_token {
+                if let Some(reason) = self.get_quote_refund_reason(&p.token_identifier, &value) {
+                    leg.refunds.push(RefundQuote { payment: p, reason });
+                    continue;
+                }
+            }
+
+            // base tokens cannot be capped, they are not dust
+            let allowed_value = if is_base_token { None } else { self.get_allowed_swap_value(&p.token_identifier) };
+            if let Some(allowed_value) = allowed_value {
+                if value > allowed_value {
+                    let accepted_amount = &p.amount * &allowed_value / &value;
+                    let refund = EsdtTokenPayment::new(p.token_identifier.clone(), 0, &p.amount - &accepted_amount);
+                    leg.refunds.push(RefundQuote { payment: refund, reason: RefundReason::CapExceeded });
+                    if accepted_amount == 0 {
+                        continue;
+                    }
+
+                    // the smaller amount is quoted again, it can never be worth more than the cap
+                    p.amount = accepted_amount;
+                    let (spot_value, safe_value) = self.get_spot_and_safe_amount_out_through_route(&route, p.token_identifier.clone(), p.amount.clone());
+                    if self.is_price_deviation_exceeded(&p.token_identifier, &spot_value, &safe_value) {
+                        leg.refunds.push(RefundQuote { payment: p, reason: RefundReason::PriceDeviation });
+                        continue;
+                    }
+
+                    value = min_amount(min_amount(spot_value, safe_value), allowed_value);
+                    if let Some(reason) = self.get_quote_refund_reason(&p.token_identifier, &value) {
+                        leg.refunds.push(RefundQuote { payment: p, reason });
+                        continue;
+                    }
+                }
+            }
+
+            let swap_now = self.should_swap(mode, &p.token_identifier);
+            if swap_now {
+                value = self.swap_through_route(&route, p.token_identifier.clone(), p.amount.clone()).amount;
+                leg.received_amount += &value;
+            }
+
+            if mode != SwapMode::Quote {
+                self.add_epoch_inflow(&p.token_identifier, &value);
+                if !swap_now && !is_base_token {
+                    self.add_inventory(&p.token_identifier, &p.amount, &value);
+                }
+            }
+
+            leg.total_amount += &value;
+            leg.quotes.push(TokenQuote {
+                token_id: p.token_identifier,
+                amount_in: p.amount,
+                amount_out: value,
+            });
+        }
+
+        leg
+    }
+
+    /// Why a payment of token_id quoting value is refunded, if it is.
+    fn get_quote_refund_reason(&self, token_id: &TokenIdentifier, value: &BigUint) -> Option<RefundReason> {
+        if *value == 0 {
+            return Some(RefundReason::ZeroQuote);
+        }
+
+        if value < &self.min_quote(token_id).get() {
+            return Some(RefundReason::BelowMinQuote);
+        }
+
+        None
+    }
 
-            let value = self.get_amount_out(pair.address, p.token_identifier, p.amount);
-            total_amount += value;
+    #[inline]
+    fn should_swap(&self, mode: SwapMode, token_id: &TokenIdentifier) -> bool {
+        match mode {
+            SwapMode::Quote => false,
+            SwapMode::Auto => self.is_atomic_swap(token_id),
+            SwapMode::Atomic => true,
         }
+    }
 
-        (total_amount, refund_payments)
+    /// Maps the requested output to the token paid by the contract. EGLD is paid as WEGLD, unwrapped on payout.
+    fn get_output_token(&self, output_token: &EgldOrEsdtTokenIdentifier) -> TokenIdentifier {
+        let output_token = if output_token.is_egld() {
+            self.wrapped_token().get()
+        } else {
+            output_token.clone().unwrap_esdt()
+        };
+        require!(self.output_tokens().contains(&output_token), "Output token not allowed");
+
+        output_token
     }
 
-    fn add_usdc_to_first_payment(&self, payments: &mut ManagedVec<EsdtTokenPayment>, amount: BigUint) {
+    fn add_base_to_first_payment(&self, payments: &mut ManagedVec<EsdtTokenPayment>, base_token: &TokenIdentifier, amount: BigUint) {
         if amount == BigUint::zero() {
             return;
         }
 
-        let usdc_token = self.usdc_token().get();
         if payments.is_empty() {
-            payments.push(EsdtTokenPayment::new(usdc_token, 0, amount));
+            payments.push(EsdtTokenPayment::new(base_token.clone(), 0, amount));
             return;
         } 
 
         let first_payment = payments.get(0);
-        if first_payment.token_identifier != usdc_token {
-            payments.push(EsdtTokenPayment::new(usdc_token, 0, amount));
+        if &first_payment.token_identifier != base_token {
+            payments.push(EsdtTokenPayment::new(base_token.clone(), 0, amount));
             return;
         }
 
-        let result = payments.set(0, &EsdtTokenPayment::new(usdc_token.clone(), 0, first_payment.amount + amount.clone()));
+        let result = payments.set(0, &EsdtTokenPayment::new(base_token.clone(), 0, first_payment.amount + amount.clone()));
         if result.is_err() {
-            payments.push(EsdtTokenPayment::new(usdc_token, 0, amount));
+            payments.push(EsdtTokenPayment::new(base_token.clone(), 0, amount));
+        }
+    }
+
+    /// Computes a leg for every base token over the payments registered under it and adds the total of each
+    /// base token to wegld_swaps. A base token's leg is swapped right away if WEGLD is atomic, so the base token
+    /// is received before being swapped to WEGLD. Returns the legs merged, and the total of each base token.
+    fn compute_base_legs(
+        &self,
+        payments: &ManagedVec<EsdtTokenPayment>,
+        wegld_swaps: &mut ManagedVec<EsdtTokenPayment>,
+        quote_only: bool
+    ) -> (SwapLeg<Self::Api>, ManagedVec<EsdtTokenPayment>) {
+        let mut base_legs = SwapLeg::default();
+        let mut known_payments = ManagedVec::<Self::Api, EsdtTokenPayment>::new();
+        let mut payment_bases = ManagedVec::<Self::Api, TokenIdentifier>::new();
+        let wrapped_token = self.wrapped_token().get();
+        for p in payments.iter() {
+            if !self.is_known_token(&p.token_identifier) {
+                base_legs.refunds.push(RefundQuote { payment: p, reason: RefundReason::UnknownToken });
+                continue;
+            }
+
+            // tokens of the WEGLD leg counted as base payments are the caller's mistake, while tokens registered
+            // under an output that is no longer a base token, e.g. after removeBaseTokens, are refunded
+            let base_token = self.get_token_config(&p.token_identifier).output_token;
+            require!(base_token != wrapped_token, "Invalid payments");
+            if !self.base_tokens().contains(&base_token) {
+                base_legs.refunds.push(RefundQuote { payment: p, reason: RefundReason::NotBaseToken });
+                continue;
+            }
+
+            known_payments.push(p);
+            payment_bases.push(base_token);
+        }
+
+        let wegld_atomic = self.is_atomic_swap(&wrapped_token);
+        let mut base_amounts = ManagedVec::new();
+        for base_token in self.base_tokens().iter() {
+            let mut base_swaps = ManagedVec::new();
+            for (p, p_base) in known_payments.iter().zip(payment_bases.iter()) {
+                if *p_base == base_token {
+                    base_swaps.push(p);
+                }
+            }
+
+            if base_swaps.is_empty() {
+                continue;
+            }
+
+            let mode = if quote_only {
+                SwapMode::Quote
+            } else if wegld_atomic || self.is_atomic_swap(&base_token) {
+                SwapMode::Atomic
+            } else {
+                SwapMode::Auto
+            };
+            let leg = self.compute_swap_amount(base_token.clone(), &base_swaps, mode);
+            if mode == SwapMode::Auto {
+                self.deposit_reserve(&base_token, &leg.received_amount);
+            }
+
+            self.add_base_to_first_payment(wegld_swaps, &base_token, leg.total_amount.clone());
+            base_amounts.push(EsdtTokenPayment::new(base_token, 0, leg.total_amount));
+            base_legs.quotes.extend(&leg.quotes);
+            base_legs.refunds.extend(&leg.refunds);
         }
+
+        (base_legs, base_amounts)
     }
 
-    /// Receives a MultiEsdtNftTransfer and swaps the tokens to WEGLD. First, swaps all the tokens for WEGLD.
+    /// Receives a MultiEsdtNftTransfer and swaps the tokens to output_token. First, swaps all the tokens for WEGLD,
+    /// the ones registered under a base token going through it, then converts the WEGLD amount to output_token
+    /// through its output route.
     /// After that, computes the protocol fee from the resulted amount. If a referral tag is used, the referral cut is also computed.
     /// Any user will be able to call this endpoint. Arguments:
-    /// num_wegld - The first num_wegld payments will be swapped to WEGD
-    /// amount_out_min - The minimum amount of WEGLD that the user wants to receive
+    /// num_wegld - The first num_wegld payments will be swapped to WEGD, the others to their base token
+    /// output_token - The token the user wants to receive. Must be one of the allowed output tokens,
+    /// or EGLD, in which case the WEGLD amount is unwrapped through the wrapping contract
+    /// amount_out_min - The minimum amount of output_token that the user wants to receive
     /// tag - The tag of the referral
+    /// Returns the payment sent to the caller, the fees and the refunded payments with why each of them was refused.
     #[payable("*")]
     #[endpoint(swapDustTokens)]
-    fn swap_dust_tokens(&self, num_wegld: usize, amount_out_min: BigUint, tag: OptionalValue<ManagedBuffer>) {
-        self.require_state_active();
+    fn swap_dust_tokens(
+        &self,
+        num_wegld: usize,
+        output_token: EgldOrEsdtTokenIdentifier,
+        amount_out_min: BigUint,
+        tag: OptionalValue<ManagedBuffer>
+    ) -> SwapResult<Self::Api> {
+        let payments = self.call_value().all_esdt_transfers();
+        let (wegld_swaps, base_swaps) = self.split_payments(&payments, num_wegld);
+        let caller = self.blockchain().get_caller();
+        let recipients = SwapRecipients {
+            destination: caller.clone(),
+            refund_address: caller,
+            forward_call: None,
+        };
+        self.swap_dust(wegld_swaps, base_swaps, output_token, amount_out_min, tag.into_option(), &recipients)
+    }
+
+    /// Same as swapDustTokens, but the output is sent to destination and the refunded payments to refund_address.
+    /// The referral volume and the fees are computed the same way.
+    #[payable("*")]
+    #[endpoint(swapDustTokensTo)]
+    fn swap_dust_tokens_to(
+        &self,
+        num_wegld: usize,
+        output_token: EgldOrEsdtTokenIdentifier,
+        amount_out_min: BigUint,
+        destination: ManagedAddress,
+        refund_address: ManagedAddress,
+        tag: OptionalValue<ManagedBuffer>
+    ) -> SwapResult<Self::Api> {
+        require!(!destination.is_zero(), "Invalid destination");
+        require!(!refund_address.is_zero(), "Invalid refund address");
+
+        let payments = self.call_value().all_esdt_transfers();
+        let (wegld_swaps, base_swaps) = self.split_payments(&payments, num_wegld);
+        let recipients = SwapRecipients {
+            destination,
+            refund_address,
+            forward_call: None,
+        };
+        self.swap_dust(wegld_swaps, base_swaps, output_token, amount_out_min, tag.into_option(), &recipients)
+    }
+
+    /// Same as swapDustTokens, but the output is sent with a call to an endpoint of target allowed by the admins.
+    /// The endpoint receives the caller's address first, followed by args. Refunds go back to the caller and
+    /// the whole transaction fails if the call does. The output cannot be EGLD.
+    #[payable("*")]
+    #[endpoint(swapDustTokensAndCall)]
+    fn swap_dust_tokens_and_call(
+        &self,
+        num_wegld: usize,
+        output_token: EgldOrEsdtTokenIdentifier,
+        amount_out_min: BigUint,
+        target: ManagedAddress,
+        endpoint_name: ManagedBuffer,
+        args: MultiValueEncoded<ManagedBuffer>
+    ) -> SwapResult<Self::Api> {
+        require!(!output_token.is_egld(), "Cannot forward EGLD");
+        require!(self.call_target_endpoints(&target).contains(&endpoint_name), "Call target not allowed");
+
+        let payments = self.call_value().all_esdt_transfers();
+        let (wegld_swaps, base_swaps) = self.split_payments(&payments, num_wegld);
+        let recipients = SwapRecipients {
+            destination: target,
+            refund_address: self.blockchain().get_caller(),
+            forward_call: Some(ForwardCall {
+                endpoint_name,
+                args: args.to_vec(),
+            }),
+        };
+        self.swap_dust(wegld_swaps, base_swaps, output_token, amount_out_min, None, &recipients)
+    }
 
+    /// Same as swapDustTokens, but the payments can be sent in any order. Each payment goes to WEGLD
+    /// or to a base token depending on the output it is registered under.
+    #[payable("*")]
+    #[endpoint(swapDustTokensAnyOrder)]
+    fn swap_dust_tokens_any_order(
+        &self,
+        output_token: EgldOrEsdtTokenIdentifier,
+        amount_out_min: BigUint,
+        tag: OptionalValue<ManagedBuffer>
+    ) -> SwapResult<Self::Api> {
         let payments = self.call_value().all_esdt_transfers();
+        let (wegld_swaps, base_swaps) = self.group_payments(&payments);
+        let caller = self.blockchain().get_caller();
+        let recipients = SwapRecipients {
+            destination: caller.clone(),
+            refund_address: caller,
+            forward_call: None,
+        };
+        self.swap_dust(wegld_swaps, base_swaps, output_token, amount_out_min, tag.into_option(), &recipients)
+    }
+
+    /// Splits the payments into the first num_wegld ones, swapped to WEGLD, and the others.
+    fn split_payments(&self, payments: &ManagedVec<EsdtTokenPayment>, num_wegld: usize) -> (ManagedVec<EsdtTokenPayment>, ManagedVec<EsdtTokenPayment>) {
         let num_payments = payments.len();
         require!(num_wegld <= num_payments, "Invalid num_wegld");
 
-        let mut wegld_swaps = payments.slice(0, num_wegld).unwrap_or_else(ManagedVec::new);
-        let usdc_swaps = payments.slice(num_wegld, num_payments).unwrap_or_else(ManagedVec::new);
+        let wegld_swaps = payments.slice(0, num_wegld).unwrap_or_else(ManagedVec::new);
+        let base_swaps = payments.slice(num_wegld, num_payments).unwrap_or_else(ManagedVec::new);
+        (wegld_swaps, base_swaps)
+    }
+
+    /// Splits the payments into the ones registered under WEGLD and the others. Unknown tokens go with
+    /// the others, which are grouped by base token and refund unknown tokens.
+    fn group_payments(&self, payments: &ManagedVec<EsdtTokenPayment>) -> (ManagedVec<EsdtTokenPayment>, ManagedVec<EsdtTokenPayment>) {
+        let wrapped_egld = self.wrapped_token().get();
+        let mut wegld_swaps = ManagedVec::new();
+        let mut base_swaps = ManagedVec::new();
+        for p in payments.iter() {
+            if self.is_known_token(&p.token_identifier) && self.get_token_config(&p.token_identifier).output_token == wrapped_egld {
+                wegld_swaps.push(p);
+            } else {
+                base_swaps.push(p);
+            }
+        }
+
+        (wegld_swaps, base_swaps)
+    }
 
-        let (usdc_amount, usdc_refund) = self.compute_swap_amount(self.usdc_token().get(), &usdc_swaps);
-        self.add_usdc_to_first_payment(&mut wegld_swaps, usdc_amount);
+    /// Swaps the payments received by the calling endpoint, already split into the WEGLD and base token parts.
+    /// The output is sent to the destination and the refunds to the refund address of recipients.
+    fn swap_dust(
+        &self,
+        mut wegld_swaps: ManagedVec<EsdtTokenPayment>,
+        base_swaps: ManagedVec<EsdtTokenPayment>,
+        output_token: EgldOrEsdtTokenIdentifier,
+        amount_out_min: BigUint,
+        referral_tag: Option<ManagedBuffer>,
+        recipients: &SwapRecipients<Self::Api>
+    ) -> SwapResult<Self::Api> {
+        self.require_state_active();
+
+        let payments = self.call_value().all_esdt_transfers();
+        let unwrap_egld = output_token.is_egld();
+        let requested_output_token = output_token;
+        let output_token = self.get_output_token(&requested_output_token);
+        let caller = self.blockchain().get_caller();
+
+        let (base_legs, _) = self.compute_base_legs(&base_swaps, &mut wegld_swaps, false);
 
         let wrapped_egld = self.wrapped_token().get();
-        let (total_amount, mut wegld_refund) = self.compute_swap_amount(wrapped_egld.clone(), &wegld_swaps);
+        let wegld_mode = if self.is_atomic_swap(&wrapped_egld) { SwapMode::Atomic } else { SwapMode::Auto };
+        let wegld_leg = self.compute_swap_amount(wrapped_egld.clone(), &wegld_swaps, wegld_mode);
+
+        // without an accepted payment nothing was swapped or recorded, so every payment is in the refunds
+        if self.refund_only_allowed().get() && wegld_leg.quotes.is_empty() && base_legs.quotes.is_empty() {
+            require!(amount_out_min == 0, "Slippage exceeded");
+
+            let mut refunds = wegld_leg.refunds;
+            refunds.extend(&base_legs.refunds);
+            return self.refund_dust_tokens(&caller, recipients, payments, refunds, requested_output_token, referral_tag);
+        }
+
+        self.deposit_reserve(&wrapped_egld, &wegld_leg.received_amount);
+
+        let wegld_amount = wegld_leg.total_amount.clone();
+        let total_amount = self.convert_to_output_token(&output_token, &wegld_amount, SwapMode::Auto);
+        self.withdraw_reserve(&output_token, &total_amount);
 
-        let mut fee_amount = self.get_fee_from_input(&total_amount);
-        let amount_after_fees = &total_amount - &fee_amount;
+        let total_fee = self.get_fee_from_input(&total_amount);
+        let amount_after_fees = &total_amount - &total_fee;
         require!(amount_after_fees >= amount_out_min, "Slippage exceeded");
 
-        if let Some(tag_name) = tag.into_option() {
-            self.accumulated_volume(&tag_name).update(|x| *x += total_amount);
-            fee_amount = self.subtract_referral_fee_and_update_collected_fees(fee_amount, tag_name);
+        let mut fee_amount = total_fee.clone();
+        if let Some(tag_name) = &referral_tag {
+            self.accumulated_volume(tag_name).update(|x| *x += wegld_amount);
+            fee_amount = self.subtract_referral_fee_and_update_collected_fees(fee_amount, tag_name.clone(), &output_token);
         }
 
-        let caller = self.blockchain().get_caller();
         require!(amount_after_fees > 0, "Zero amount cannot be claimed");
-        
-        self.send().direct_esdt(&caller, &wrapped_egld, 0, &amount_after_fees);
 
-        wegld_refund.extend(&usdc_refund);
-        if !wegld_refund.is_empty() {
-            self.send().direct_multi(&caller, &wegld_refund);
+        // the fees, refunds and event are settled first, as the forwarded call can reenter the contract
+        let mut refunds = wegld_leg.refund_payments();
+        refunds.extend(&base_legs.refund_payments());
+        if !refunds.is_empty() {
+            self.send().direct_multi(&recipients.refund_address, &refunds);
+        }
+
+        self.add_protocol_fees(&output_token, &fee_amount);
+        self.fee_tokens().insert(output_token.clone());
+
+        let referral_fee = &total_fee - &fee_amount;
+        self.swap_dust_tokens_event(&caller, &SwapDustTokensEvent {
+            payments,
+            output_token: requested_output_token.clone(),
+            amount_out: amount_after_fees.clone(),
+            referral_fee: referral_fee.clone(),
+            protocol_fee: fee_amount.clone(),
+            referral_tag,
+            refunds,
+        });
+
+        if let Some(forward_call) = &recipients.forward_call {
+            let output = EsdtTokenPayment::new(output_token, 0, amount_after_fees.clone());
+            self.forward_output(&caller, &recipients.destination, forward_call, output);
+        } else if unwrap_egld {
+            self.unwrap_and_send_egld(&recipients.destination, amount_after_fees.clone());
+        } else {
+            self.send().direct_esdt(&recipients.destination, &output_token, 0, &amount_after_fees);
+        }
+
+        let mut refund_quotes = wegld_leg.refunds;
+        refund_quotes.extend(&base_legs.refunds);
+        SwapResult {
+            output: EgldOrEsdtTokenPayment::new(requested_output_token, 0, amount_after_fees),
+            protocol_fee: fee_amount,
+            referral_fee,
+            refunds: refund_quotes,
+        }
+    }
+
+    fn forward_output(
+        &self,
+        caller: &ManagedAddress,
+        target: &ManagedAddress,
+        forward_call: &ForwardCall<Self::Api>,
+        output: EsdtTokenPayment
+    ) {
+        let mut arg_buffer = ManagedArgBuffer::new();
+        arg_buffer.push_arg(caller);
+        for arg in forward_call.args.iter() {
+            arg_buffer.push_arg_raw((*arg).clone());
+        }
+
+        self.send()
+            .contract_call::<IgnoreValue>(target.clone(), forward_call.endpoint_name.clone())
+            .with_esdt_transfer(output)
+            .with_raw_arguments(arg_buffer)
+            .execute_on_dest_context::<IgnoreValue>();
+    }
+
+    /// Sends every payment back to the refund address when none of them was accepted. refunds holds the reason
+    /// each payment was refused for.
+    fn refund_dust_tokens(
+        &self,
+        caller: &ManagedAddress,
+        recipients: &SwapRecipients<Self::Api>,
+        payments: ManagedVec<EsdtTokenPayment>,
+        refunds: ManagedVec<RefundQuote<Self::Api>>,
+        output_token: EgldOrEsdtTokenIdentifier,
+        referral_tag: Option<ManagedBuffer>
+    ) -> SwapResult<Self::Api> {
+        if !payments.is_empty() {
+            self.send().direct_multi(&recipients.refund_address, &payments);
+        }
+
+        self.swap_dust_tokens_event(caller, &SwapDustTokensEvent {
+            payments: payments.clone(),
+            output_token: output_token.clone(),
+            amount_out: BigUint::zero(),
+            protocol_fee: BigUint::zero(),
+            referral_tag,
+            referral_fee: BigUint::zero(),
+            refunds: payments,
+        });
+
+        SwapResult {
+            output: EgldOrEsdtTokenPayment::new(output_token, 0, BigUint::zero()),
+            protocol_fee: BigUint::zero(),
+            referral_fee: BigUint::zero(),
+            refunds,
+        }
+    }
+
+    /// Simulates swapDustTokens for the given payments, without swapping or writing to storage.
+    /// Returns the quote of every payment, the refunds and the fees, as the endpoint would compute them.
+    #[view(getSwapQuote)]
+    fn get_swap_quote(
+        &self,
+        num_wegld: usize,
+        output_token: EgldOrEsdtTokenIdentifier,
+        payments: ManagedVec<EsdtTokenPayment>,
+        tag: OptionalValue<ManagedBuffer>
+    ) -> SwapQuote<Self::Api> {
+        let output_token = self.get_output_token(&output_token);
+        let (wegld_swaps, base_swaps) = self.split_payments(&payments, num_wegld);
+        self.quote_swap(&output_token, wegld_swaps, base_swaps, tag.into_option())
+    }
+
+    /// Simulates swapDustTokensAnyOrder for the given payments, the same way getSwapQuote does.
+    #[view(getSwapQuoteAnyOrder)]
+    fn get_swap_quote_any_order(
+        &self,
+        output_token: EgldOrEsdtTokenIdentifier,
+        payments: ManagedVec<EsdtTokenPayment>,
+        tag: OptionalValue<ManagedBuffer>
+    ) -> SwapQuote<Self::Api> {
+        let output_token = self.get_output_token(&output_token);
+        let (wegld_swaps, base_swaps) = self.group_payments(&payments);
+        self.quote_swap(&output_token, wegld_swaps, base_swaps, tag.into_option())
+    }
+
+    fn quote_swap(
+        &self,
+        output_token: &TokenIdentifier,
+        mut wegld_swaps: ManagedVec<EsdtTokenPayment>,
+        base_swaps: ManagedVec<EsdtTokenPayment>,
+        tag: Option<ManagedBuffer>
+    ) -> SwapQuote<Self::Api> {
+        let (base_legs, base_amounts) = self.compute_base_legs(&base_swaps, &mut wegld_swaps, true);
+        let wegld_leg = self.compute_swap_amount(self.wrapped_token().get(), &wegld_swaps, SwapMode::Quote);
+
+        let total_amount = self.convert_to_output_token(output_token, &wegld_leg.total_amount, SwapMode::Quote);
+        let total_fee = self.get_fee_from_input(&total_amount);
+        let referral_fee = match tag {
+            Some(tag_name) => self.get_referral_fee(&total_fee, &tag_name),
+            None => BigUint::zero(),
+        };
+
+        let mut quotes = wegld_leg.quotes;
+        quotes.extend(&base_legs.quotes);
+        let mut refunds = wegld_leg.refunds;
+        refunds.extend(&base_legs.refunds);
+
+        SwapQuote {
+            quotes,
+            refunds,
+            base_amounts,
+            wegld_amount: wegld_leg.total_amount,
+            amount_out: &total_amount - &total_fee,
+            protocol_fee: &total_fee - &referral_fee,
+            referral_fee,
+            total_amount,
+        }
+    }
+
+    /// Converts a WEGLD amount to output_token, following the output token's route.
+    /// The WEGLD is swapped if mode requires it for output_token, otherwise the amount is only quoted.
+    fn convert_to_output_token(&self, output_token: &TokenIdentifier, wegld_amount: &BigUint, mode: SwapMode) -> BigUint {
+        let route = self.output_token_route(output_token).get();
+        if route.is_empty() || wegld_amount == &BigUint::zero() {
+            return wegld_amount.clone();
+        }
+
+        let wrapped_egld = self.wrapped_token().get();
+        let (spot_amount, safe_amount) = self.get_spot_and_safe_amount_out_through_route(&route, wrapped_egld.clone(), wegld_amount.clone());
+        require!(!self.is_price_deviation_exceeded(output_token, &spot_amount, &safe_amount), "Price deviation too high");
+
+        if self.should_swap(mode, output_token) {
+            self.withdraw_reserve(&wrapped_egld, wegld_amount);
+            let proceeds = self.swap_through_route(&route, wrapped_egld, wegld_amount.clone());
+            self.deposit_reserve(&proceeds.token_identifier, &proceeds.amount);
+
+            return proceeds.amount;
         }
 
-        self.collected_fee_amount().update(|x| *x += fee_amount);
+        min_amount(spot_amount, safe_amount)
     }
 
+    /// Sells the contract's balance of the given tokens, following each token's best quoting route
+    /// to its registered output. The reserve and fees held in a token are never sold.
+    /// A token is only sold if its balance is worth more than its threshold and its spot price
+    /// is within the allowed deviation from its safe price. Base token proceeds are forwarded to WEGLD if configured.
+    /// The caller receives the keeper bounty of every sold token.
     #[endpoint(sellDustTokens)]
     fn sell_dust_tokens(&self, tokens_to_sell: MultiValueEncoded<TokenIdentifier>) {
-        let wrapped_egld = self.wrapped_token().get();
-        let known_tokens_mapper = self.known_tokens();
+        self.require_can_sell();
+
+        let caller = self.blockchain().get_caller();
+        let mut summary = SellSummary::default();
         for token in tokens_to_sell.into_iter() {
-            if !known_tokens_mapper.contains(&token) {
-                continue;
+            self.sell_token(&caller, token, &mut summary);
+        }
+
+        self.settle_sell(&caller, &summary);
+    }
+
+    /// Sells the known tokens of every output token, resuming from where the previous call stopped.
+    /// Stops before a token once the gas left is not above the configured minimum and stores its position
+    /// for the next call. Returns the sold and skipped tokens.
+    #[endpoint(sellDustTokensBatch)]
+    fn sell_dust_tokens_batch(&self) -> SellSummary<Self::Api> {
+        self.require_can_sell();
+
+        let caller = self.blockchain().get_caller();
+        let min_gas_left = self.get_sell_min_gas_left();
+        let outputs = self.get_registry_outputs();
+        let mut cursor = if self.sell_cursor().is_empty() {
+            SellCursor::default()
+        } else {
+            self.sell_cursor().get()
+        };
+        let mut summary = SellSummary::default();
+        while cursor.output_index < outputs.len() {
+            let tokens = self.registry_tokens(&outputs.get(cursor.output_index));
+            while cursor.token_index < tokens.len() {
+                if self.blockchain().get_gas_left() <= min_gas_left {
+                    self.sell_cursor().set(cursor);
+                    self.settle_sell(&caller, &summary);
+                    return summary;
+                }
+
+                self.sell_token(&caller, tokens.get_by_index(cursor.token_index + 1), &mut summary);
+                cursor.token_index += 1;
             }
 
-            let pair = self.pair_contract(&token).get();
-            let balance = self.blockchain().get_sc_balance(&EgldOrEsdtTokenIdentifier::esdt(token.clone()), 0);
-            if balance == BigUint::zero() {
-                continue;
+            cursor.output_index += 1;
+            cursor.token_index = 0;
+        }
+
+        self.sell_cursor().clear();
+        self.settle_sell(&caller, &summary);
+        summary.finished = true;
+
+        summary
+    }
+
+    fn require_can_sell(&self) {
+        self.require_state_active();
+        if self.keeper_only_selling().get() {
+            self.require_caller_has_keeper_permissions();
+        }
+    }
+
+    /// Sells the unaccounted balance of a known token, or the chunk of it allowed by the token's execution
+    /// policy, and adds the result to the summary. The proceeds, minus the keeper bounty, go to the reserve.
+    fn sell_token(&self, caller: &ManagedAddress, token: TokenIdentifier, summary: &mut SellSummary<Self::Api>) {
+        if !self.is_known_token(&token) {
+            summary.skipped.push(SkippedToken { token_id: token, reason: SkipReason::UnknownToken });
+            return;
+        }
+
+        let balance = self.get_unaccounted_balance(&token);
+        if balance == BigUint::zero() {
+            summary.skipped.push(SkippedToken { token_id: token, reason: SkipReason::NoBalance });
+            return;
+        }
+
+        if self.blockchain().get_block_round() < self.get_next_sell_round(&token) {
+            summary.skipped.push(SkippedToken { token_id: token, reason: SkipReason::TooSoon });
+            return;
+        }
+
+        // the route is chosen for the whole balance among the available pairs, then the chunk is sized on it
+        let (route, mut spot_value, mut safe_value) = match self.get_best_token_route(&token, &balance) {
+            Some(best_route) => best_route,
+            None => {
+                summary.skipped.push(SkippedToken { token_id: token, reason: SkipReason::PairUnavailable });
+                return;
+            }
+        };
+
+        let amount = match self.get_sell_chunk(&token, &route, balance.clone()) {
+            Some(amount) => amount,
+            None => {
+                summary.skipped.push(SkippedToken { token_id: token, reason: SkipReason::PriceImpact });
+                return;
+            }
+        };
+        if amount != balance {
+            (spot_value, safe_value) = self.get_spot_and_safe_amount_out_through_route(&route, token.clone(), amount.clone());
+        }
+        if self.is_price_deviation_exceeded(&token, &spot_value, &safe_value) {
+            summary.skipped.push(SkippedToken { token_id: token, reason: SkipReason::PriceDeviation });
+            return;
+        }
+
+        let value = min_amount(spot_value, safe_value);
+        if value <= self.get_token_threshold(&token) {
+            summary.skipped.push(SkippedToken { token_id: token, reason: SkipReason::BelowThreshold });
+            return;
+        }
+
+        let cost_basis = self.get_cost_basis(&token, &amount);
+        if self.is_sell_loss_exceeded(&cost_basis, &value) {
+            summary.skipped.push(SkippedToken { token_id: token, reason: SkipReason::BelowCostBasis });
+            return;
+        }
+
+        let proceeds = self.swap_through_route(&route, token.clone(), amount.clone());
+        let keeper_bounty = self.get_keeper_bounty(&proceeds);
+        let kept_amount = &proceeds.amount - &keeper_bounty;
+        self.deposit_reserve(&proceeds.token_identifier, &kept_amount);
+        self.record_sale(&token, &amount, &kept_amount);
+        self.record_sell_chunk(&token, &amount);
+        if keeper_bounty > 0 {
+            self.add_keeper_bounty(&proceeds.token_identifier, &keeper_bounty);
+        }
+
+        self.sell_dust_token_event(caller, &token, &SellDustTokenEvent {
+            amount_in: amount.clone(),
+            proceeds: proceeds.clone(),
+            keeper_bounty: keeper_bounty.clone(),
+        });
+        summary.sold.push(SoldToken {
+            token_id: token,
+            amount_in: amount,
+            proceeds,
+            keeper_bounty,
+        });
+    }
+
+    /// Pays the keeper bounties of the sold tokens to the caller and forwards the base token proceeds if configured.
+    fn settle_sell(&self, caller: &ManagedAddress, summary: &SellSummary<Self::Api>) {
+        let mut bounties = ManagedVec::new();
+        for sold in summary.sold.iter() {
+            if sold.keeper_bounty > 0 {
+                bounties.push(EsdtTokenPayment::new(sold.proceeds.token_identifier, 0, sold.keeper_bounty));
             }
+        }
 
-            let value = self.get_amount_out(pair.clone().address, token.clone(), balance.clone());
-            let threshold = self.token_threshold(&token).get();
-            if value > threshold {
-                let amount_out_min = self.get_amount_out_min(&value);
-                self.swap_tokens_fixed_input(pair.address, token, balance, wrapped_egld.clone(), amount_out_min);
+        if !bounties.is_empty() {
+            self.send().direct_multi(caller, &bounties);
+            self.keeper_bounty_event(caller, &bounties);
+        }
+
+        if !self.forward_base_proceeds().get() {
+            return;
+        }
+
+        for base_token in self.base_tokens().iter() {
+            let mut base_proceeds = BigUint::zero();
+            for sold in summary.sold.iter() {
+                if sold.proceeds.token_identifier == base_token {
+                    base_proceeds += &sold.proceeds.amount - &sold.keeper_bounty;
+                }
             }
+
+            if base_proceeds > 0 {
+                self.sell_base_proceeds(caller, base_token, base_proceeds);
+            }
+        }
+    }
+
+    /// Sells a base token from the reserve for WEGLD through its route. The base token is kept if it has
+    /// no available route to WEGLD or its price deviates too much from the safe price.
+    fn sell_base_proceeds(&self, caller: &ManagedAddress, base_token: TokenIdentifier, amount: BigUint) {
+        if !self.is_known_token(&base_token) {
+            return;
+        }
+
+        let (route, spot_value, safe_value) = match self.get_best_token_route(&base_token, &amount) {
+            Some(best_route) => best_route,
+            None => return,
+        };
+        let ends_in_wegld = route.get(route.len() - 1).output_token == self.wrapped_token().get();
+        if !ends_in_wegld || self.is_price_deviation_exceeded(&base_token, &spot_value, &safe_value) {
+            return;
         }
+
+        self.withdraw_reserve(&base_token, &amount);
+        let proceeds = self.swap_through_route(&route, base_token.clone(), amount.clone());
+        self.deposit_reserve(&proceeds.token_identifier, &proceeds.amount);
+        self.sell_dust_token_event(caller, &base_token, &SellDustTokenEvent {
+            amount_in: amount,
+            proceeds,
+            keeper_bounty: BigUint::zero(),
+        });
+    }
+
+    fn swap_through_route(
+        &self,
+        route: &Route<Self::Api>,
+        token_in: TokenIdentifier,
+        amount_in: BigUint
+    ) -> EsdtTokenPayment {
+        let mut payment = EsdtTokenPayment::new(token_in, 0, amount_in);
+        for hop in route.iter() {
+            let value = self.get_amount_out(hop.address.clone(), payment.token_identifier.clone(), payment.amount.clone());
+            let amount_out_min = self.get_amount_out_min(&value);
+            payment = self.swap_tokens_fixed_input(
+                hop.address,
+                payment.token_identifier,
+                payment.amount,
+                hop.output_token,
+                amount_out_min
+            );
+        }
+
+        payment
     }
 
     #[inline]
@@ -1,10 +1,43 @@
+use crate::config::Route;
+
 elrond_wasm::imports!();
+elrond_wasm::derive_imports!();
+
+/// State of a DEX pair. Only active pairs can be swapped through by anyone.
+#[derive(TopEncode, TopDecode, TypeAbi, PartialEq, Clone, Copy, Debug)]
+pub enum PairState {
+    Inactive,
+    Active,
+    PartialActive,
+}
+
+pub fn min_amount<M: ManagedTypeApi>(first: BigUint<M>, second: BigUint<M>) -> BigUint<M> {
+    if first < second {
+        first
+    } else {
+        second
+    }
+}
 
 mod pair_proxy {
     elrond_wasm::imports!();
 
+    use super::PairState;
+
     #[elrond_wasm::proxy]
     pub trait PairProxy {
+
+        #[view(getFirstTokenId)]
+        fn get_first_token_id(&self) -> TokenIdentifier;
+
+        #[view(getSecondTokenId)]
+        fn get_second_token_id(&self) -> TokenIdentifier;
+
+        #[view(getState)]
+        fn get_state(&self) -> PairState;
+
+        #[view(getReservesAndTotalSupply)]
+        fn get_reserves_and_total_supply(&self) -> MultiValue3<BigUint, BigUint, BigUint>;
         
         #[view(getAmountOut)]
         fn get_amount_out_view(
@@ -13,6 +46,13 @@ mod pair_proxy {
             amount_in: BigUint
         ) -> BigUint;
 
+        #[view(getSafePriceByDefaultOffset)]
+        fn get_safe_price_by_default_offset(
+            &self,
+            pair_address: ManagedAddress,
+            input_payment: EsdtTokenPayment
+        ) -> EsdtTokenPayment;
+
         #[endpoint(swapTokensFixedInput)]
         fn swap_tokens_fixed_input(
             &self,
@@ -21,6 +61,101 @@ mod pair_proxy {
         ) -> EsdtTokenPayment;
     }
 }
+
+mod router_proxy {
+    elrond_wasm::imports!();
+
+    #[elrond_wasm::proxy]
+    pub trait RouterProxy {
+
+        #[view(getPair)]
+        fn get_pair(&self, first_token_id: TokenIdentifier, second_token_id: TokenIdentifier) -> ManagedAddress;
+    }
+}
+
+/// Proxy for other contracts to call the dust converter, e.g. `dust_converter::proxy::dust_converter_proxy::Proxy`.
+pub mod dust_converter_proxy {
+    elrond_wasm::imports!();
+
+    use crate::quote::{SwapQuote, SwapResult};
+
+    #[elrond_wasm::proxy]
+    pub trait DustConverterProxy {
+
+        #[payable("*")]
+        #[endpoint(swapDustTokens)]
+        fn swap_dust_tokens(
+            &self,
+            num_wegld: usize,
+            output_token: EgldOrEsdtTokenIdentifier,
+            amount_out_min: BigUint,
+            tag: OptionalValue<ManagedBuffer>
+        ) -> SwapResult<Self::Api>;
+
+        #[payable("*")]
+        #[endpoint(swapDustTokensTo)]
+        fn swap_dust_tokens_to(
+            &self,
+            num_wegld: usize,
+            output_token: EgldOrEsdtTokenIdentifier,
+            amount_out_min: BigUint,
+            destination: ManagedAddress,
+            refund_address: ManagedAddress,
+            tag: OptionalValue<ManagedBuffer>
+        ) -> SwapResult<Self::Api>;
+
+        #[payable("*")]
+        #[endpoint(swapDustTokensAndCall)]
+        fn swap_dust_tokens_and_call(
+            &self,
+            num_wegld: usize,
+            output_token: EgldOrEsdtTokenIdentifier,
+            amount_out_min: BigUint,
+            target: ManagedAddress,
+            endpoint_name: ManagedBuffer,
+            args: MultiValueEncoded<ManagedBuffer>
+        ) -> SwapResult<Self::Api>;
+
+        #[payable("*")]
+        #[endpoint(swapDustTokensAnyOrder)]
+        fn swap_dust_tokens_any_order(
+            &self,
+            output_token: EgldOrEsdtTokenIdentifier,
+            amount_out_min: BigUint,
+            tag: OptionalValue<ManagedBuffer>
+        ) -> SwapResult<Self::Api>;
+
+        #[view(getSwapQuote)]
+        fn get_swap_quote(
+            &self,
+            num_wegld: usize,
+            output_token: EgldOrEsdtTokenIdentifier,
+            payments: ManagedVec<EsdtTokenPayment>,
+            tag: OptionalValue<ManagedBuffer>
+        ) -> SwapQuote<Self::Api>;
+
+        #[view(getSwapQuoteAnyOrder)]
+        fn get_swap_quote_any_order(
+            &self,
+            output_token: EgldOrEsdtTokenIdentifier,
+            payments: ManagedVec<EsdtTokenPayment>,
+            tag: OptionalValue<ManagedBuffer>
+        ) -> SwapQuote<Self::Api>;
+    }
+}
+
+mod wrapping_proxy {
+    elrond_wasm::imports!();
+
+    #[elrond_wasm::proxy]
+    pub trait WrappingProxy {
+
+        #[payable("*")]
+        #[endpoint(unwrapEgld)]
+        fn unwrap_egld(&self);
+    }
+}
+
 #[elrond_wasm::module]
 pub trait ProxyModule {
 
@@ -35,6 +170,120 @@ pub trait ProxyModule {
             .execute_on_dest_context()
     }
 
+    /// Returns the two tokens traded by the pair.
+    fn get_pair_tokens(&self, pair_address: ManagedAddress) -> (TokenIdentifier, TokenIdentifier) {
+        let first_token_id = self.pair_proxy(pair_address.clone())
+            .get_first_token_id()
+            .execute_on_dest_context();
+        let second_token_id = self.pair_proxy(pair_address)
+            .get_second_token_id()
+            .execute_on_dest_context();
+
+        (first_token_id, second_token_id)
+    }
+
+    fn get_pair_state(&self, pair_address: ManagedAddress) -> PairState {
+        self.pair_proxy(pair_address)
+            .get_state()
+            .execute_on_dest_context()
+    }
+
+    /// Returns the pair's reserve of token_id. A pair can only be quoted if it is active and holds reserves
+    /// of both its tokens, otherwise None is returned.
+    fn get_available_reserve(&self, pair_address: ManagedAddress, token_id: &TokenIdentifier) -> Option<BigUint> {
+        if self.get_pair_state(pair_address.clone()) != PairState::Active {
+            return None;
+        }
+
+        let (first_reserve, second_reserve, _) = self.pair_proxy(pair_address.clone())
+            .get_reserves_and_total_supply()
+            .execute_on_dest_context::<MultiValue3<BigUint, BigUint, BigUint>>()
+            .into_tuple();
+        if first_reserve == 0 || second_reserve == 0 {
+            return None;
+        }
+
+        let first_token_id: TokenIdentifier = self.pair_proxy(pair_address)
+            .get_first_token_id()
+            .execute_on_dest_context();
+        if &first_token_id == token_id {
+            Some(first_reserve)
+        } else {
+            Some(second_reserve)
+        }
+    }
+
+    /// Looks up the pair of the two tokens in the router. Returns the zero address if there is none.
+    fn get_router_pair(
+        &self,
+        router_address: ManagedAddress,
+        first_token_id: TokenIdentifier,
+        second_token_id: TokenIdentifier
+    ) -> ManagedAddress {
+        self.router_proxy(router_address)
+            .get_pair(first_token_id, second_token_id)
+            .execute_on_dest_context()
+    }
+
+    /// Quotes amount_in at the pair's time weighted average price instead of its current reserves.
+    fn get_safe_price(
+        &self,
+        pair_address: ManagedAddress,
+        token_in: TokenIdentifier,
+        amount_in: BigUint
+    ) -> BigUint {
+        let input_payment = EsdtTokenPayment::new(token_in, 0, amount_in);
+        let output_payment: EsdtTokenPayment = self.pair_proxy(pair_address.clone())
+            .get_safe_price_by_default_offset(pair_address, input_payment)
+            .execute_on_dest_context();
+
+        output_payment.amount
+    }
+
+    /// Quotes amount_in through the route, once at the spot price and once at the safe price of every pair.
+    fn get_spot_and_safe_amount_out_through_route(
+        &self,
+        route: &Route<Self::Api>,
+        token_in: TokenIdentifier,
+        amount_in: BigUint
+    ) -> (BigUint, BigUint) {
+        let mut token = token_in;
+        let mut spot_amount = amount_in.clone();
+        let mut safe_amount = amount_in;
+        for hop in route.iter() {
+            spot_amount = self.get_amount_out(hop.address.clone(), token.clone(), spot_amount);
+            safe_amount = self.get_safe_price(hop.address, token, safe_amount);
+            token = hop.output_token;
+        }
+
+        (spot_amount, safe_amount)
+    }
+
+    /// Same as get_spot_and_safe_amount_out_through_route, but returns None instead of quoting a pair that is not
+    /// available or whose reserve of the hop's output is not above the safe quote, as the pair could not pay it.
+    fn try_get_spot_and_safe_amount_out_through_route(
+        &self,
+        route: &Route<Self::Api>,
+        token_in: TokenIdentifier,
+        amount_in: BigUint
+    ) -> Option<(BigUint, BigUint)> {
+        let mut token = token_in;
+        let mut spot_amount = amount_in.clone();
+        let mut safe_amount = amount_in;
+        for hop in route.iter() {
+            let reserve_out = self.get_available_reserve(hop.address.clone(), &hop.output_token)?;
+            safe_amount = self.get_safe_price(hop.address.clone(), token.clone(), safe_amount);
+            if safe_amount >= reserve_out {
+                return None;
+            }
+
+            spot_amount = self.get_amount_out(hop.address, token, spot_amount);
+            token = hop.output_token;
+        }
+
+        Some((spot_amount, safe_amount))
+    }
+
     fn swap_tokens_fixed_input(
         &self,
         pair_address: ManagedAddress,
@@ -51,6 +300,26 @@ pub trait ProxyModule {
             .execute_on_dest_context()
     }
 
+    fn unwrap_egld(
+        &self,
+        wrapping_address: ManagedAddress,
+        wrapped_token: TokenIdentifier,
+        amount: BigUint
+    ) {
+        let payment = EsdtTokenPayment::new(wrapped_token, 0, amount);
+
+        self.wrapping_proxy(wrapping_address)
+            .unwrap_egld()
+            .with_esdt_transfer(payment)
+            .execute_on_dest_context::<()>();
+    }
+
     #[proxy]
     fn pair_proxy(&self, to: ManagedAddress) -> pair_proxy::Proxy<Self::Api>;
+
+    #[proxy]
+    fn router_proxy(&self, to: ManagedAddress) -> router_proxy::Proxy<Self::Api>;
+
+    #[proxy]
+    fn wrapping_proxy(&self, to: ManagedAddress) -> wrapping_proxy::Proxy<Self::Api>;
 }
\ No newline at end of file
@@ -0,0 +1,104 @@
+elrond_wasm::imports!();
+elrond_wasm::derive_imports!();
+
+/// How compute_swap_amount handles the payments. Quote never swaps, Auto only swaps the tokens
+/// in atomic swap mode and Atomic swaps every payment.
+#[derive(Clone, Copy, PartialEq)]
+pub enum SwapMode {
+    Quote,
+    Auto,
+    Atomic,
+}
+
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, ManagedVecItem, Clone, Copy, PartialEq, Debug)]
+pub enum RefundReason {
+    UnknownToken,
+    PriceDeviation,
+    CapExceeded,
+    PairUnavailable,
+    ZeroQuote,
+    BelowMinQuote,
+    NotBaseToken,
+}
+
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, ManagedVecItem, Clone, PartialEq, Debug)]
+pub struct TokenQuote<M: ManagedTypeApi> {
+    pub token_id: TokenIdentifier<M>,
+    pub amount_in: BigUint<M>,
+    pub amount_out: BigUint<M>,
+}
+
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, ManagedVecItem, Clone, PartialEq, Debug)]
+pub struct RefundQuote<M: ManagedTypeApi> {
+    pub payment: EsdtTokenPayment<M>,
+    pub reason: RefundReason,
+}
+
+/// The result of compute_swap_amount for one leg of a swap (the payments of a base token or of WEGLD).
+pub struct SwapLeg<M: ManagedTypeApi> {
+    pub total_amount: BigUint<M>,
+    pub received_amount: BigUint<M>,
+    pub quotes: ManagedVec<M, TokenQuote<M>>,
+    pub refunds: ManagedVec<M, RefundQuote<M>>,
+}
+
+impl<M: ManagedTypeApi> Default for SwapLeg<M> {
+    fn default() -> Self {
+        SwapLeg {
+            total_amount: BigUint::zero(),
+            received_amount: BigUint::zero(),
+            quotes: ManagedVec::new(),
+            refunds: ManagedVec::new(),
+        }
+    }
+}
+
+impl<M: ManagedTypeApi> SwapLeg<M> {
+    pub fn refund_payments(&self) -> ManagedVec<M, EsdtTokenPayment<M>> {
+        let mut payments = ManagedVec::new();
+        for refund in self.refunds.iter() {
+            payments.push(refund.payment);
+        }
+
+        payments
+    }
+}
+
+/// Breakdown of a simulated swapDustTokens call. The WEGLD leg quotes come first and are in WEGLD,
+/// they include the total of every base token quoted to WEGLD. The base token leg quotes follow and
+/// are in their base token, whose totals are in base_amounts.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, Clone, PartialEq, Debug)]
+pub struct SwapQuote<M: ManagedTypeApi> {
+    pub quotes: ManagedVec<M, TokenQuote<M>>,
+    pub refunds: ManagedVec<M, RefundQuote<M>>,
+    pub base_amounts: ManagedVec<M, EsdtTokenPayment<M>>,
+    pub wegld_amount: BigUint<M>,
+    pub total_amount: BigUint<M>,
+    pub protocol_fee: BigUint<M>,
+    pub referral_fee: BigUint<M>,
+    pub amount_out: BigUint<M>,
+}
+
+/// Outcome of a swapDustTokens call: the payment sent to the caller, the fees kept from it
+/// and the refunded payments with the reason each of them was refused.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, Clone, PartialEq, Debug)]
+pub struct SwapResult<M: ManagedTypeApi> {
+    pub output: EgldOrEsdtTokenPayment<M>,
+    pub protocol_fee: BigUint<M>,
+    pub referral_fee: BigUint<M>,
+    pub refunds: ManagedVec<M, RefundQuote<M>>,
+}
+
+/// An endpoint of the destination called with the output instead of transferring it. The endpoint
+/// receives the address of the original caller, followed by args.
+pub struct ForwardCall<M: ManagedTypeApi> {
+    pub endpoint_name: ManagedBuffer<M>,
+    pub args: ManagedVec<M, ManagedBuffer<M>>,
+}
+
+/// Where swap_dust sends the output and the refunded payments.
+pub struct SwapRecipients<M: ManagedTypeApi> {
+    pub destination: ManagedAddress<M>,
+    pub refund_address: ManagedAddress<M>,
+    pub forward_call: Option<ForwardCall<M>>,
+}
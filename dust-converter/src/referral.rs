@@ -1,4 +1,5 @@
 use crate::config::{MAX_FEE_PERCENTAGE, MAX_PERCENTAGE, self};
+use crate::{events, proxy, reserve};
 
 elrond_wasm::imports!();
 elrond_wasm::derive_imports!();
@@ -19,6 +20,9 @@ pub trait ReferralModule:
     permissions_module::PermissionsModule
     + pausable::PausableModule
     + config::ConfigModule
+    + proxy::ProxyModule
+    + reserve::ReserveModule
+    + events::EventsModule
 {
 
     #[endpoint(registerReferralTag)]
@@ -36,25 +40,27 @@ pub trait ReferralModule:
         for tier in self.tier_details().iter() {
             if tier.min_volume == BigUint::zero() {
                 self.referral_tag_percent(&tag).set(tier.fee_percent);
+                self.register_referral_tag_event(&caller, &tag);
                 return;
             }
         }
         sc_panic!("No tier with min volume 0");
     }
 
+    /// Sends the fees collected by the caller's tag. The WEGLD fees are sent as EGLD if unwrap_egld is set.
     #[endpoint(claimReferralFees)]
-    fn claim_referral_fees(&self) {
+    fn claim_referral_fees(&self, unwrap_egld: OptionalValue<bool>) {
         self.require_state_active();
         
         let caller = self.blockchain().get_caller();
         require!(!self.user_tag_mapping(&caller).is_empty(), "Not a tag owner");
         let user_tag = self.user_tag_mapping(&caller).get();
 
-        let amount = self.collected_tag_fees(&user_tag).get();
-        require!(amount > 0, "No fees to claim");
+        let fees = self.take_collected_tag_fees(&user_tag);
+        require!(!fees.is_empty(), "No fees to claim");
 
-        self.send().direct_esdt(&caller, &self.wrapped_token().get(), 0, &amount);
-        self.collected_tag_fees(&user_tag).clear();
+        self.send_payments(&caller, &fees, unwrap_egld.into_option().unwrap_or_default());
+        self.claim_referral_fees_event(&caller, &user_tag, &fees);
     }
 
     #[endpoint(updateTier)]
@@ -78,6 +84,7 @@ pub trait ReferralModule:
         
         require!(fee_percent != self.referral_tag_percent(&user_tag).get(), "No tier upgrade found");
         self.referral_tag_percent(&user_tag).set(fee_percent);
+        self.referral_fee_percentage_event(&user_tag, fee_percent);
         
         tier_name
     }
@@ -89,13 +96,15 @@ pub trait ReferralModule:
         for tier in tiers.into_iter() {
             let (name, min_volume, fee_percent) = tier.into_tuple();
             require!(fee_percent < MAX_FEE_PERCENTAGE, "Invalid fee percentage");
-            let is_new = self.tier_details().insert(TierDetails { 
+            let tier = TierDetails { 
                 name, 
                 min_volume, 
                 fee_percent
-            });
+            };
+            let is_new = self.tier_details().insert(tier.clone());
     
             require!(is_new, "Tier already exists");
+            self.add_tier_details_event(&tier);
         }
     }
 
@@ -108,6 +117,7 @@ pub trait ReferralModule:
         for tier in tier_details.iter() {
             if tier.name == name {
                 tier_details.swap_remove(&tier);
+                self.remove_tier_details_event(&name);
                 return;
             }
         }
@@ -121,54 +131,141 @@ pub trait ReferralModule:
         require!(new_percentage < MAX_FEE_PERCENTAGE, "Invalid new percentage given");
         require!(!self.referral_tag_percent(&tag).is_empty(), "Tag not found");
         self.referral_tag_percent(&tag).set(new_percentage);
+        self.referral_fee_percentage_event(&tag, new_percentage);
     }
 
     #[endpoint(removeReferralTag)]
     fn remove_referral_tag(&self, user_address: ManagedAddress) {
         self.require_caller_has_owner_or_admin_permissions();
 
-        let wrapped_egld = self.wrapped_token().get();
         let tag = self.user_tag_mapping(&user_address).get();
-        let collected_amount = self.collected_tag_fees(&tag).get();
-        if collected_amount > 0 {
-            self.send().direct_esdt(&user_address, &wrapped_egld, 0, &collected_amount);
-        }
+        let fees = self.take_collected_tag_fees(&tag);
+        self.send_payments(&user_address, &fees, false);
 
         self.accumulated_volume(&tag).clear();
         self.referral_tag_percent(&tag).clear();
-        self.collected_tag_fees(&tag).clear();
         self.user_tag_mapping(&user_address).clear();
+        self.remove_referral_tag_event(&user_address, &tag, &fees);
+    }
+
+    /// Moves the protocol fees and the fees of the given tags from the storage layout that predates
+    /// per-token fees under the WEGLD key. The old values are cleared, so tags can be migrated in several calls.
+    /// Tags migrated after migrateReserve take their fees out of the reserve, which counted them as free balance.
+    #[endpoint(migrateFeeStorage)]
+    fn migrate_fee_storage(&self, tags: MultiValueEncoded<ManagedBuffer>) {
+        self.require_caller_has_owner_permissions();
+
+        let wrapped_token = self.wrapped_token().get();
+        let protocol_fees = self.legacy_collected_fee_amount().take();
+        if protocol_fees > 0 {
+            self.add_protocol_fees(&wrapped_token, &protocol_fees);
+            self.fee_tokens().insert(wrapped_token.clone());
+            self.migrate_protocol_fees_event(&wrapped_token, &protocol_fees);
+        }
+
+        for tag in tags {
+            let tag_fees = self.legacy_collected_tag_fees(&tag).take();
+            if tag_fees > 0 {
+                if self.reserve_migrated().get() {
+                    self.withdraw_reserve(&wrapped_token, &tag_fees);
+                }
+                self.collected_tag_fees(&tag, &wrapped_token).update(|x| *x += &tag_fees);
+                self.add_referral_fees(&wrapped_token, &tag_fees);
+                self.fee_tokens().insert(wrapped_token.clone());
+                self.migrate_tag_fees_event(&tag, &wrapped_token, &tag_fees);
+            }
+        }
+    }
+
+    /// Seeds the WEGLD reserve with the part of the balance that is not fees, after upgrading from the layout
+    /// without reserve accounting. The protocol fees must be migrated first. Tag fees still in the old layout
+    /// become reserve too, until migrateFeeStorage moves them out of it.
+    #[endpoint(migrateReserve)]
+    fn migrate_reserve(&self) {
+        self.require_caller_has_owner_permissions();
+
+        let wrapped_token = self.wrapped_token().get();
+        require!(self.legacy_collected_fee_amount().is_empty(), "Fees not migrated");
+        require!(self.reserve_amount(&wrapped_token).is_empty(), "Reserve already set");
+
+        let amount = self.get_unaccounted_balance(&wrapped_token);
+        self.deposit_reserve(&wrapped_token, &amount);
+        self.reserve_migrated().set(true);
+        self.migrate_reserve_event(&wrapped_token, &amount);
     }
 
     #[view(getCollectedFeeAmount)]
     fn get_collected_fee_amount(
         &self,
         address: ManagedAddress
-    ) -> BigUint {
-        let tag_mapping = self.user_tag_mapping(&address);
+    ) -> MultiValueEncoded<EsdtTokenPayment> {
+        let tag = self.user_tag_mapping(&address).get();
+
+        let mut fees = MultiValueEncoded::new();
+        for token in self.fee_tokens().iter() {
+            let amount = self.collected_tag_fees(&tag, &token).get();
+            if amount > 0 {
+                fees.push(EsdtTokenPayment::new(token, 0, amount));
+            }
+        }
 
-        self.collected_tag_fees(&tag_mapping.get()).get()
+        fees
     }
 
+    fn take_collected_tag_fees(&self, tag: &ManagedBuffer) -> ManagedVec<EsdtTokenPayment> {
+        let mut fees = ManagedVec::new();
+        for token in self.fee_tokens().iter() {
+            let amount = self.collected_tag_fees(tag, &token).get();
+            if amount > 0 {
+                self.collected_tag_fees(tag, &token).clear();
+                self.withdraw_referral_fees(&token, &amount);
+                fees.push(EsdtTokenPayment::new(token, 0, amount));
+            }
+        }
+
+        fees
+    }
 
-    fn subtract_referral_fee_and_update_collected_fees(&self, fee_amount: BigUint, tag: ManagedBuffer) -> BigUint {
-        let tag_percentage = self.referral_tag_percent(&tag).get();
-        if tag_percentage == 0 {
+    fn subtract_referral_fee_and_update_collected_fees(
+        &self,
+        fee_amount: BigUint,
+        tag: ManagedBuffer,
+        token_id: &TokenIdentifier
+    ) -> BigUint {
+        let referral_amount = self.get_referral_fee(&fee_amount, &tag);
+        if referral_amount == 0 {
             return fee_amount;
         }
-        
-        let referral_amount = &fee_amount * tag_percentage / MAX_PERCENTAGE;
-        self.collected_tag_fees(&tag).update(|x| *x += &referral_amount);
+
+        self.collected_tag_fees(&tag, token_id).update(|x| *x += &referral_amount);
+        self.add_referral_fees(token_id, &referral_amount);
 
         fee_amount - referral_amount
     }
 
+    /// The part of fee_amount that goes to the tag.
+    fn get_referral_fee(&self, fee_amount: &BigUint, tag: &ManagedBuffer) -> BigUint {
+        let tag_percentage = self.referral_tag_percent(tag).get();
+        fee_amount * tag_percentage / MAX_PERCENTAGE
+    }
+
     #[view(getUserTag)]
     #[storage_mapper("user_tag_mapping")]
     fn user_tag_mapping(&self, user: &ManagedAddress) -> SingleValueMapper<ManagedBuffer>;
 
     #[storage_mapper("collected_tag_fees")]
-    fn collected_tag_fees(&self, tag: &ManagedBuffer) -> SingleValueMapper<BigUint>;
+    fn collected_tag_fees(&self, tag: &ManagedBuffer, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    // Fee storage prior to per-token fees, only read by migrateFeeStorage.
+
+    #[storage_mapper("collected_fee_amount")]
+    fn legacy_collected_fee_amount(&self) -> SingleValueMapper<BigUint>;
+
+    #[storage_mapper("collected_tag_fees")]
+    fn legacy_collected_tag_fees(&self, tag: &ManagedBuffer) -> SingleValueMapper<BigUint>;
+
+    #[storage_mapper("reserve_migrated")]
+    fn reserve_migrated(&self) -> SingleValueMapper<bool>;
 
     #[view(getReferralFeePercentage)]
     #[storage_mapper("referral_tags_percent")]
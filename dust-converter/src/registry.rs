@@ -0,0 +1,144 @@
+use crate::{config, events, proxy, reserve};
+
+elrond_wasm::imports!();
+elrond_wasm::derive_imports!();
+
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, ManagedVecItem, Clone, Copy, PartialEq, Debug)]
+pub enum RegistryIssueKind {
+    MissingConfig,
+    OutputMismatch,
+    DuplicateEntry,
+    InvalidRoute,
+    NotInRegistry,
+    LegacyEntry,
+}
+
+/// A broken invariant of the token registry. output_token is the registry the token was found in,
+/// or the output of its configuration for tokens missing from the registry.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, ManagedVecItem, Clone, PartialEq, Debug)]
+pub struct RegistryIssue<M: ManagedTypeApi> {
+    pub token_id: TokenIdentifier<M>,
+    pub output_token: TokenIdentifier<M>,
+    pub kind: RegistryIssueKind,
+}
+
+#[elrond_wasm::module]
+pub trait RegistryModule:
+    permissions_module::PermissionsModule
+    + pausable::PausableModule
+    + config::ConfigModule
+    + proxy::ProxyModule
+    + reserve::ReserveModule
+    + events::EventsModule
+{
+
+    /// Audits the token registry of every output token. Each registered token must have a configuration
+    /// with the same output and a route ending in it, and be registered under a single output token.
+    /// Configurations can only be looked up by token, so the tokens given are checked for being missing
+    /// from the registry of their output. Tokens left in the layout prior to TokenConfig are reported too.
+    #[view(checkTokenRegistry)]
+    fn check_token_registry(&self, tokens: MultiValueEncoded<TokenIdentifier>) -> ManagedVec<RegistryIssue<Self::Api>> {
+        let mut issues = ManagedVec::new();
+        let outputs = self.get_audited_outputs();
+        for output_token in outputs.iter() {
+            for token in self.registry_tokens(&output_token).iter() {
+                if let Some(kind) = self.get_registry_entry_issue(&output_token, &token) {
+                    issues.push(RegistryIssue { token_id: token, output_token: (*output_token).clone(), kind });
+                }
+            }
+
+            for token in self.legacy_all_tokens(&output_token).get().iter() {
+                issues.push(RegistryIssue { token_id: (*token).clone(), output_token: (*output_token).clone(), kind: RegistryIssueKind::LegacyEntry });
+            }
+        }
+
+        for token in tokens {
+            if self.token_config(&token).is_empty() {
+                continue;
+            }
+
+            let output_token = self.token_config(&token).get().output_token;
+            if !self.registry_tokens(&output_token).contains(&token) {
+                issues.push(RegistryIssue { token_id: token, output_token, kind: RegistryIssueKind::NotInRegistry });
+            }
+        }
+
+        issues
+    }
+
+    /// Rebuilds the registry of every output token from the token configurations. Entries without a
+    /// configuration, or whose configuration has another output, are removed and the given tokens are
+    /// added to the registry of their output. Tokens with an invalid route are disabled. Tokens in the
+    /// layout prior to TokenConfig are left to migrateTokenRegistry.
+    #[endpoint(repairTokenRegistry)]
+    fn repair_token_registry(&self, tokens: MultiValueEncoded<TokenIdentifier>) {
+        self.require_caller_has_owner_permissions();
+
+        let issues = self.check_token_registry(tokens);
+        for issue in issues.iter() {
+            match issue.kind {
+                RegistryIssueKind::MissingConfig
+                | RegistryIssueKind::OutputMismatch
+                | RegistryIssueKind::DuplicateEntry => {
+                    self.registry_tokens(&issue.output_token).swap_remove(&issue.token_id);
+                    self.reset_sell_cursor();
+                    if !self.token_config(&issue.token_id).is_empty() {
+                        let output_token = self.token_config(&issue.token_id).get().output_token;
+                        self.registry_tokens(&output_token).insert(issue.token_id);
+                    }
+                },
+                RegistryIssueKind::InvalidRoute => {
+                    let mut config = self.get_token_config(&issue.token_id);
+                    if config.enabled {
+                        config.enabled = false;
+                        self.set_token_config(&issue.token_id, &config);
+                    }
+                },
+                RegistryIssueKind::NotInRegistry => {
+                    self.registry_tokens(&issue.output_token).insert(issue.token_id);
+                },
+                RegistryIssueKind::LegacyEntry => {},
+            }
+        }
+
+        self.repair_token_registry_event(&self.blockchain().get_caller(), &issues);
+    }
+
+    /// The output tokens whose registry is audited: the ones selling goes through and the allowed outputs.
+    fn get_audited_outputs(&self) -> ManagedVec<TokenIdentifier> {
+        let mut outputs = self.get_registry_outputs();
+        for output_token in self.output_tokens().iter() {
+            if !outputs.contains(&output_token) {
+                outputs.push(output_token);
+            }
+        }
+
+        outputs
+    }
+
+    fn get_registry_entry_issue(
+        &self,
+        output_token: &TokenIdentifier,
+        token_id: &TokenIdentifier
+    ) -> Option<RegistryIssueKind> {
+        if self.token_config(token_id).is_empty() {
+            return Some(RegistryIssueKind::MissingConfig);
+        }
+
+        let config = self.token_config(token_id).get();
+        if &config.output_token != output_token {
+            // the entry in the registry of the configured output is the valid one
+            return if self.registry_tokens(&config.output_token).contains(token_id) {
+                Some(RegistryIssueKind::DuplicateEntry)
+            } else {
+                Some(RegistryIssueKind::OutputMismatch)
+            };
+        }
+
+        if config.route.is_empty() || &config.route.get(config.route.len() - 1).output_token != output_token {
+            return Some(RegistryIssueKind::InvalidRoute);
+        }
+
+        None
+    }
+}
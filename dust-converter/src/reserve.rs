@@ -0,0 +1,83 @@
+elrond_wasm::imports!();
+
+/// Keeps the output token balances of the contract split in three buckets: the free reserve
+/// used for user payouts, the protocol fees and the referral fees. Fees are never paid from the reserve
+/// and the reserve cannot be spent on the fees.
+#[elrond_wasm::module]
+pub trait ReserveModule {
+
+    fn deposit_reserve(&self, token_id: &TokenIdentifier, amount: &BigUint) {
+        if amount == &BigUint::zero() {
+            return;
+        }
+
+        self.reserve_amount(token_id).update(|x| *x += amount);
+    }
+
+    fn withdraw_reserve(&self, token_id: &TokenIdentifier, amount: &BigUint) {
+        let reserve = self.reserve_amount(token_id).get();
+        require!(&reserve >= amount, "Insufficient reserve");
+
+        self.reserve_amount(token_id).set(reserve - amount);
+    }
+
+    fn add_protocol_fees(&self, token_id: &TokenIdentifier, amount: &BigUint) {
+        self.collected_fee_amount(token_id).update(|x| *x += amount);
+    }
+
+    fn add_referral_fees(&self, token_id: &TokenIdentifier, amount: &BigUint) {
+        self.referral_fees_owed(token_id).update(|x| *x += amount);
+    }
+
+    fn withdraw_referral_fees(&self, token_id: &TokenIdentifier, amount: &BigUint) {
+        let owed = self.referral_fees_owed(token_id).get();
+        require!(&owed >= amount, "Insufficient referral fees");
+
+        self.referral_fees_owed(token_id).set(owed - amount);
+    }
+
+    fn add_keeper_bounty(&self, token_id: &TokenIdentifier, amount: &BigUint) {
+        self.keeper_bounties_paid(token_id).update(|x| *x += amount);
+    }
+
+    /// The part of the contract's balance of token_id that is neither reserve nor fees.
+    fn get_unaccounted_balance(&self, token_id: &TokenIdentifier) -> BigUint {
+        let balance = self.blockchain().get_sc_balance(&EgldOrEsdtTokenIdentifier::esdt(token_id.clone()), 0);
+        let accounted = self.reserve_amount(token_id).get()
+            + self.collected_fee_amount(token_id).get()
+            + self.referral_fees_owed(token_id).get();
+        if balance <= accounted {
+            return BigUint::zero();
+        }
+
+        balance - accounted
+    }
+
+    /// Returns the available reserve, the protocol fees and the referral fees owed in the given token.
+    #[view(getReserveStatus)]
+    fn get_reserve_status(&self, token_id: TokenIdentifier) -> MultiValue3<BigUint, BigUint, BigUint> {
+        (
+            self.reserve_amount(&token_id).get(),
+            self.collected_fee_amount(&token_id).get(),
+            self.referral_fees_owed(&token_id).get(),
+        ).into()
+    }
+
+    #[view(getReserveAmount)]
+    #[storage_mapper("reserve_amount")]
+    fn reserve_amount(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    #[view(getCollectedProtocolFees)]
+    #[storage_mapper("collected_fee_amount")]
+    fn collected_fee_amount(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    #[view(getReferralFeesOwed)]
+    #[storage_mapper("referral_fees_owed")]
+    fn referral_fees_owed(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    /// Total paid to the callers of sellDustTokens. Bounties are taken from the sell proceeds,
+    /// before they reach the reserve.
+    #[view(getKeeperBountiesPaid)]
+    #[storage_mapper("keeper_bounties_paid")]
+    fn keeper_bounties_paid(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+}
@@ -0,0 +1,196 @@
+use crate::config::{self, Route, MAX_PERCENTAGE};
+use crate::{events, proxy, reserve};
+
+elrond_wasm::imports!();
+elrond_wasm::derive_imports!();
+
+pub const DEFAULT_SELL_MIN_GAS_LEFT: u64 = 30_000_000u64;
+pub const MAX_CHUNK_HALVINGS: usize = 8;
+
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, ManagedVecItem, Clone, Copy, PartialEq, Debug)]
+pub enum SkipReason {
+    UnknownToken,
+    NoBalance,
+    PriceDeviation,
+    BelowThreshold,
+    BelowCostBasis,
+    TooSoon,
+    PriceImpact,
+    PairUnavailable,
+}
+
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, ManagedVecItem, Clone, PartialEq, Debug)]
+pub struct SoldToken<M: ManagedTypeApi> {
+    pub token_id: TokenIdentifier<M>,
+    pub amount_in: BigUint<M>,
+    pub proceeds: EsdtTokenPayment<M>,
+    pub keeper_bounty: BigUint<M>,
+}
+
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, ManagedVecItem, Clone, PartialEq, Debug)]
+pub struct SkippedToken<M: ManagedTypeApi> {
+    pub token_id: TokenIdentifier<M>,
+    pub reason: SkipReason,
+}
+
+/// Position of sellDustTokensBatch in the token registry: the output token and the index in its registry tokens.
+#[derive(TopEncode, TopDecode, TypeAbi, Clone, Copy, PartialEq, Debug, Default)]
+pub struct SellCursor {
+    pub output_index: usize,
+    pub token_index: usize,
+}
+
+/// What a sell call did. finished is set once a batch reached the end of the registry.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, Clone, PartialEq, Debug)]
+pub struct SellSummary<M: ManagedTypeApi> {
+    pub sold: ManagedVec<M, SoldToken<M>>,
+    pub skipped: ManagedVec<M, SkippedToken<M>>,
+    pub finished: bool,
+}
+
+impl<M: ManagedTypeApi> Default for SellSummary<M> {
+    fn default() -> Self {
+        SellSummary {
+            sold: ManagedVec::new(),
+            skipped: ManagedVec::new(),
+            finished: false,
+        }
+    }
+}
+
+/// How a token's balance is sold. A sell is capped at max_chunk_amount and halved until selling it moves
+/// the price by at most max_price_impact compared to selling probe_amount. Chunks are at least
+/// min_rounds_between_chunks apart. Zero values disable the corresponding limit.
+#[derive(TopEncode, TopDecode, TypeAbi, Clone, PartialEq, Debug)]
+pub struct ExecutionPolicy<M: ManagedTypeApi> {
+    pub max_chunk_amount: BigUint<M>,
+    pub probe_amount: BigUint<M>,
+    pub max_price_impact: u64,
+    pub min_rounds_between_chunks: u64,
+}
+
+#[derive(TopEncode, TopDecode, TypeAbi, Clone, PartialEq, Debug)]
+pub struct SellProgress<M: ManagedTypeApi> {
+    pub remaining_amount: BigUint<M>,
+    pub sold_amount: BigUint<M>,
+    pub chunks_sold: u64,
+    pub last_sell_round: u64,
+    pub next_sell_round: u64,
+}
+
+#[elrond_wasm::module]
+pub trait SellModule:
+    permissions_module::PermissionsModule
+    + pausable::PausableModule
+    + config::ConfigModule
+    + proxy::ProxyModule
+    + reserve::ReserveModule
+    + events::EventsModule
+{
+
+    #[endpoint(setExecutionPolicy)]
+    fn set_execution_policy(
+        &self,
+        token_id: TokenIdentifier,
+        max_chunk_amount: BigUint,
+        probe_amount: BigUint,
+        max_price_impact: u64,
+        min_rounds_between_chunks: u64
+    ) {
+        self.require_caller_has_owner_or_admin_permissions();
+        require!(token_id.is_valid_esdt_identifier(), "Invalid token ID");
+        require!(max_price_impact <= MAX_PERCENTAGE, "Price impact percent invalid");
+        require!(max_price_impact == 0 || probe_amount > 0, "Probe amount required");
+
+        let policy = ExecutionPolicy {
+            max_chunk_amount,
+            probe_amount,
+            max_price_impact,
+            min_rounds_between_chunks,
+        };
+        self.execution_policy_event(&token_id, &policy);
+        self.execution_policy(&token_id).set(policy);
+    }
+
+    #[endpoint(removeExecutionPolicy)]
+    fn remove_execution_policy(&self, token_id: TokenIdentifier) {
+        self.require_caller_has_owner_or_admin_permissions();
+
+        self.execution_policy(&token_id).clear();
+        self.remove_execution_policy_event(&token_id);
+    }
+
+    fn get_next_sell_round(&self, token_id: &TokenIdentifier) -> u64 {
+        if self.execution_policy(token_id).is_empty() || self.last_sell_round(token_id).is_empty() {
+            return 0;
+        }
+
+        self.last_sell_round(token_id).get() + self.execution_policy(token_id).get().min_rounds_between_chunks
+    }
+
+    /// The amount to sell out of balance under the token's execution policy, or None if even the smallest
+    /// chunk tried moves the price too much. The price impact is measured on the route the chunk is sold through.
+    fn get_sell_chunk(&self, token_id: &TokenIdentifier, route: &Route<Self::Api>, balance: BigUint) -> Option<BigUint> {
+        if self.execution_policy(token_id).is_empty() {
+            return Some(balance);
+        }
+
+        let policy = self.execution_policy(token_id).get();
+        let mut chunk = balance;
+        if policy.max_chunk_amount > 0 && chunk > policy.max_chunk_amount {
+            chunk = policy.max_chunk_amount;
+        }
+
+        if policy.max_price_impact == 0 {
+            return Some(chunk);
+        }
+
+        let (probe_value, _) = self.get_spot_and_safe_amount_out_through_route(route, token_id.clone(), policy.probe_amount.clone());
+        let min_rate_percent = MAX_PERCENTAGE - policy.max_price_impact;
+        for _ in 0..MAX_CHUNK_HALVINGS {
+            if chunk == 0 {
+                return None;
+            }
+
+            // the chunk's price compared to the probe's, without dividing
+            let (chunk_value, _) = self.get_spot_and_safe_amount_out_through_route(route, token_id.clone(), chunk.clone());
+            if &chunk_value * &policy.probe_amount * MAX_PERCENTAGE >= &probe_value * &chunk * min_rate_percent {
+                return Some(chunk);
+            }
+
+            chunk /= 2u32;
+        }
+
+        None
+    }
+
+    fn record_sell_chunk(&self, token_id: &TokenIdentifier, amount: &BigUint) {
+        self.last_sell_round(token_id).set(self.blockchain().get_block_round());
+        self.sold_amount(token_id).update(|x| *x += amount);
+        self.chunks_sold(token_id).update(|x| *x += 1);
+    }
+
+    #[view(getSellProgress)]
+    fn get_sell_progress(&self, token_id: TokenIdentifier) -> SellProgress<Self::Api> {
+        SellProgress {
+            remaining_amount: self.get_unaccounted_balance(&token_id),
+            sold_amount: self.sold_amount(&token_id).get(),
+            chunks_sold: self.chunks_sold(&token_id).get(),
+            last_sell_round: self.last_sell_round(&token_id).get(),
+            next_sell_round: self.get_next_sell_round(&token_id),
+        }
+    }
+
+    #[view(getExecutionPolicy)]
+    #[storage_mapper("execution_policy")]
+    fn execution_policy(&self, token_id: &TokenIdentifier) -> SingleValueMapper<ExecutionPolicy<Self::Api>>;
+
+    #[storage_mapper("last_sell_round")]
+    fn last_sell_round(&self, token_id: &TokenIdentifier) -> SingleValueMapper<u64>;
+
+    #[storage_mapper("sold_amount")]
+    fn sold_amount(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    #[storage_mapper("chunks_sold")]
+    fn chunks_sold(&self, token_id: &TokenIdentifier) -> SingleValueMapper<u64>;
+}
@@ -1,6 +1,6 @@
 use elrond_wasm::{
-    types::{Address, MultiValueEncoded, BigUint},
-    elrond_codec::multi_types::{MultiValue3, OptionalValue}
+    types::{Address, MultiValueEncoded, TokenIdentifier, BigInt, BigUint, ManagedAddress, ManagedVec, EgldOrEsdtTokenIdentifier, EsdtTokenPayment},
+    elrond_codec::{multi_types::{MultiValue3, OptionalValue}, top_encode_to_vec_u8_or_panic}
 };
 use elrond_wasm_debug::{
     DebugApi,
@@ -13,6 +13,7 @@ use elrond_wasm_debug::{
 };
 
 static DUST_WASM_PATH: &str = "../output/dust-converter.wasm";
+pub const INITIAL_SC_BALANCE: u64 = 10_000_000_000_000_000_000u64;
 pub const TIER_1_MIN_VOLUME: u64 = 0u64;
 pub const TIER_2_MIN_VOLUME: u64 = 5_000_000_000u64;
 pub const TIER_3_MIN_VOLUME: u64 = 25_000_000_000u64;
@@ -25,10 +26,18 @@ pub const TIER_4_FEE_PERCENT: u64 = 2_500u64;
 
 use dust_converter::{
     DustConverter,
-    config::ConfigModule
+    config::{ConfigModule, PairContractData},
+    sell::SellCursor
 };
+use dust_converter::events::{SellDustTokenEvent, SwapDustTokensEvent};
 use dust_converter::referral::ReferralModule;
+use dust_converter::reserve::ReserveModule;
+use dust_converter::inventory::InventoryModule;
+use dust_converter::quote::RefundReason;
+use dust_converter::sell::SellModule;
+use dust_converter::registry::{RegistryIssueKind, RegistryModule};
 use pausable::PausableModule;
+use pair_mock::{PairMock, State};
 
 
 pub struct DustConvertorSetup<DustBuilder, MockBuilder>
@@ -49,7 +58,7 @@ where
 {
     pub fn new(dust_builder: DustBuilder, wrapped_token: &[u8], usdc_token:&[u8], pair_builder: MockBuilder) -> Self {
         let rust_zero = rust_biguint!(0);
-        let initial_sc_balance = rust_biguint!(10_000_000_000_000_000_000u64);
+        let initial_sc_balance = rust_biguint!(INITIAL_SC_BALANCE);
         let mut b_wrapper = BlockchainStateWrapper::new();
         let owner = b_wrapper.create_user_account(&rust_zero);
         b_wrapper.set_esdt_balance(&owner, wrapped_token, &initial_sc_balance);
@@ -122,55 +131,639 @@ where
         }
     }
 
+    /// Registers each token separately, as the pair mock can only report a single pair of tokens at a time.
     pub fn add_known_tokens(&mut self, output_token: &[u8], known_tokens: Vec<&[u8]>) {
+        for t in known_tokens {
+            self.add_known_token_with_route(output_token, t, vec![output_token]);
+        }
+    }
+
+    /// Returns a pair for every hop of a route starting from token_in. The first hop goes through the
+    /// default pair mock, every other hop through a new pair mock holding the hop's output token.
+    pub fn create_route_pairs(&mut self, token_in: &[u8], hops: &[&[u8]]) -> Vec<Address> {
+        let mut pairs = Vec::new();
+        let mut token = token_in;
+        for (i, hop) in hops.iter().enumerate() {
+            let pair = if i == 0 {
+                set_mock_pair_tokens(&mut self.b_wrapper, &self.owner, &self.pair_wrapper, token, hop);
+                self.pair_wrapper.address_ref().clone()
+            } else {
+                let pair_wrapper = self.b_wrapper.create_sc_account(&rust_biguint!(0u64), Some(&self.owner), pair_mock::contract_obj, "mocked wasm");
+                self.b_wrapper.set_esdt_balance(pair_wrapper.address_ref(), hop, &rust_biguint!(INITIAL_SC_BALANCE));
+                set_mock_pair_tokens(&mut self.b_wrapper, &self.owner, &pair_wrapper, token, hop);
+                pair_wrapper.address_ref().clone()
+            };
+
+            pairs.push(pair);
+            token = hop;
+        }
+
+        pairs
+    }
+
+    pub fn add_known_token_with_route(&mut self, output_token: &[u8], token: &[u8], hops: Vec<&[u8]>) {
+        let pairs = self.create_route_pairs(token, &hops);
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                let mut route = ManagedVec::new();
+                for (hop, pair) in hops.iter().zip(pairs.iter()) {
+                    route.push(PairContractData {
+                        address: managed_address!(pair),
+                        output_token: managed_token_id!(*hop)
+                    });
+                }
+
+                let mut payload_tokens = MultiValueEncoded::new();
+                payload_tokens.push(MultiValue3((managed_token_id!(token), route, managed_biguint!(2u64))));
+                sc.add_known_tokens(managed_token_id!(output_token), payload_tokens);
+            })
+            .assert_ok();
+    }
+
+    /// Registers a single hop token with the given pair, or with the zero address to look it up in the router.
+    pub fn add_known_token_with_pair(&mut self, output_token: &[u8], token: &[u8], pair: Option<&Address>, expected_err: Option<&str>) {
+        let tx = self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                let address = match pair {
+                    Some(address) => managed_address!(address),
+                    None => ManagedAddress::zero()
+                };
+                let mut route = ManagedVec::new();
+                route.push(PairContractData {
+                    address,
+                    output_token: managed_token_id!(output_token)
+                });
+
+                let mut payload_tokens = MultiValueEncoded::new();
+                payload_tokens.push(MultiValue3((managed_token_id!(token), route, managed_biguint!(2u64))));
+                sc.add_known_tokens(managed_token_id!(output_token), payload_tokens);
+            });
+
+        if let Some(msg) = expected_err {
+            tx.assert_error(4, msg);
+            return
+        }
+
+        tx.assert_ok()
+    }
+
+    /// Returns a new pair mock trading token_in and token_out that holds no reserves.
+    pub fn create_empty_pair(&mut self, token_in: &[u8], token_out: &[u8]) -> Address {
+        self.create_pair_with_reserves(token_in, token_out, 0u64, 0u64)
+    }
+
+    /// Returns a new pair mock trading token_in and token_out that holds the given reserves of them.
+    pub fn create_pair_with_reserves(&mut self, token_in: &[u8], token_out: &[u8], reserve_in: u64, reserve_out: u64) -> Address {
+        let pair_wrapper = self.b_wrapper.create_sc_account(&rust_biguint!(0u64), Some(&self.owner), pair_mock::contract_obj, "mocked wasm");
+        set_mock_pair_tokens(&mut self.b_wrapper, &self.owner, &pair_wrapper, token_in, token_out);
+        self.b_wrapper
+            .execute_tx(&self.owner, &pair_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_reserves(managed_biguint!(reserve_in), managed_biguint!(reserve_out));
+            })
+            .assert_ok();
+
+        pair_wrapper.address_ref().clone()
+    }
+
+    /// Uses the default pair mock as the router, returning itself for each pair of tokens.
+    pub fn set_router_pairs(&mut self, pairs: Vec<(&[u8], &[u8])>) {
         let p_wrapper = self.pair_wrapper.address_ref();
         self.b_wrapper
+            .execute_tx(&self.owner, &self.pair_wrapper, &rust_biguint!(0u64), |sc| {
+                for (first_token, second_token) in pairs {
+                    sc.set_pair(managed_token_id!(first_token), managed_token_id!(second_token), managed_address!(p_wrapper));
+                }
+            })
+            .assert_ok();
+
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_router_address(managed_address!(p_wrapper));
+            })
+            .assert_ok();
+    }
+
+    pub fn set_pair_state(&mut self, state: State) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.pair_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_state(state);
+            })
+            .assert_ok();
+    }
+
+    pub fn check_token_threshold(&mut self, token: &[u8], expected_threshold: u64) {
+        self.b_wrapper
+            .execute_query(&self.c_wrapper, |sc| {
+                assert_eq!(sc.get_token_threshold(&managed_token_id!(token)), managed_biguint!(expected_threshold));
+            })
+            .assert_ok();
+    }
+
+    pub fn check_token_route(&mut self, token: &[u8], expected_pairs: Vec<&Address>) {
+        self.b_wrapper
+            .execute_query(&self.c_wrapper, |sc| {
+                let route = sc.get_token_route(&managed_token_id!(token));
+                assert_eq!(route.len(), expected_pairs.len());
+
+                for (hop, pair) in route.iter().zip(expected_pairs) {
+                    assert_eq!(hop.address, managed_address!(pair));
+                }
+            })
+            .assert_ok();
+    }
+
+    pub fn remove_known_tokens(&mut self, output_token: &[u8], known_tokens: Vec<&[u8]>) {
+        self.remove_known_tokens_with_err(output_token, known_tokens, None)
+    }
+
+    pub fn remove_known_tokens_with_err(&mut self, output_token: &[u8], known_tokens: Vec<&[u8]>, expected_err: Option<&str>) {
+        let tx = self.b_wrapper
             .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
                 let mut payload_tokens = MultiValueEncoded::new();
                 for t in known_tokens {
-                    payload_tokens.push(MultiValue3(
-                        (
-                            managed_token_id!(t),
-                            managed_address!(p_wrapper),
-                            managed_biguint!(2u64))
-                        )
-                    );
+                    payload_tokens.push(managed_token_id!(t));
+
+                }
+                sc.remove_known_tokens(managed_token_id!(output_token), payload_tokens);
+            });
+
+        if let Some(msg) = expected_err {
+            tx.assert_error(4, msg);
+            return
+        }
+
+        tx.assert_ok()
+    }
+
+    /// Adds or removes a token from the registry of output_token, leaving its configuration as is.
+    pub fn set_registry_entry(&mut self, output_token: &[u8], token: &[u8], present: bool) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                let mut registry = sc.registry_tokens(&managed_token_id!(output_token));
+                if present {
+                    registry.insert(managed_token_id!(token));
+                } else {
+                    registry.swap_remove(&managed_token_id!(token));
+                }
+            })
+            .assert_ok();
+    }
+
+    pub fn check_registry_issues(&mut self, tokens: Vec<&[u8]>, expected_issues: Vec<(&[u8], &[u8], RegistryIssueKind)>) {
+        self.b_wrapper
+            .execute_query(&self.c_wrapper, |sc| {
+                let mut multi = MultiValueEncoded::new();
+                for token in tokens {
+                    multi.push(managed_token_id!(token));
+                }
+
+                let issues = sc.check_token_registry(multi);
+                assert_eq!(issues.len(), expected_issues.len());
+
+                for (issue, (token, output_token, kind)) in issues.iter().zip(expected_issues) {
+                    assert_eq!(issue.token_id, managed_token_id!(token));
+                    assert_eq!(issue.output_token, managed_token_id!(output_token));
+                    assert_eq!(issue.kind, kind);
+                }
+            })
+            .assert_ok();
+    }
+
+    pub fn repair_token_registry(&mut self, tokens: Vec<&[u8]>) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                let mut multi = MultiValueEncoded::new();
+                for token in tokens {
+                    multi.push(managed_token_id!(token));
+                }
+
+                sc.repair_token_registry(multi);
+            })
+            .assert_ok();
+    }
+
+    pub fn update_token_threshold(&mut self, token: &[u8], threshold: u64) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.update_token_threshold(managed_token_id!(token), managed_biguint!(threshold));
+            })
+            .assert_ok();
+    }
+
+    pub fn set_token_enabled(&mut self, token: &[u8], enabled: bool) {
+        self.set_token_enabled_with_err(token, enabled, None)
+    }
+
+    pub fn set_token_enabled_with_err(&mut self, token: &[u8], enabled: bool, expected_err: Option<&str>) {
+        let tx = self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_token_enabled(managed_token_id!(token), enabled);
+            });
+
+        if let Some(msg) = expected_err {
+            tx.assert_error(4, msg);
+            return
+        }
+
+        tx.assert_ok()
+    }
+
+    /// Writes known_tokens in the registry layout prior to TokenConfig and migrates them.
+    pub fn migrate_legacy_tokens(&mut self, output_token: &[u8], known_tokens: Vec<&[u8]>) {
+        let p_wrapper = self.pair_wrapper.address_ref();
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                let first_token = known_tokens[0];
+                let mut all_tokens = ManagedVec::new();
+                for t in known_tokens {
+                    let token = managed_token_id!(t);
+                    sc.legacy_known_tokens().add(&token);
+                    sc.legacy_pair_contract(&token).set(PairContractData {
+                        address: managed_address!(p_wrapper),
+                        output_token: managed_token_id!(output_token)
+                    });
+                    sc.legacy_token_threshold(&token).set(managed_biguint!(2u64));
+                    all_tokens.push(token);
+                }
+                sc.legacy_all_tokens(&managed_token_id!(output_token)).set(all_tokens);
+
+                sc.migrate_token_registry(managed_token_id!(output_token));
+                assert!(sc.legacy_all_tokens(&managed_token_id!(output_token)).is_empty());
+
+                let mut route = ManagedVec::<DebugApi, PairContractData<DebugApi>>::new();
+                route.push(PairContractData {
+                    address: managed_address!(p_wrapper),
+                    output_token: managed_token_id!(output_token)
+                });
+                check_event(
+                    b"addKnownToken",
+                    vec![output_token, first_token],
+                    &top_encode_to_vec_u8_or_panic(&route)
+                );
+            })
+            .assert_ok();
+    }
+
+    /// Sets the fees in the storage layout prior to per-token fees and migrates them.
+    pub fn migrate_legacy_fees(&mut self, protocol_fees: u64, tag: &[u8], tag_fees: u64) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.legacy_collected_fee_amount().set(managed_biguint!(protocol_fees));
+                sc.legacy_collected_tag_fees(&managed_buffer!(tag)).set(managed_biguint!(tag_fees));
+
+                let mut tags = MultiValueEncoded::new();
+                tags.push(managed_buffer!(tag));
+                sc.migrate_fee_storage(tags);
+                assert!(sc.legacy_collected_fee_amount().is_empty());
+                assert!(sc.legacy_collected_tag_fees(&managed_buffer!(tag)).is_empty());
+
+                let wrapped_token = sc.wrapped_token().get();
+                let wrapped_token = wrapped_token.as_managed_buffer().to_boxed_bytes();
+                if protocol_fees > 0 {
+                    check_event(b"migrateProtocolFees", vec![wrapped_token.as_slice()], &top_encode_to_vec_u8_or_panic(&BigUint::<DebugApi>::from(protocol_fees)));
+                }
+                check_event(b"migrateTagFees", vec![tag, wrapped_token.as_slice()], &top_encode_to_vec_u8_or_panic(&BigUint::<DebugApi>::from(tag_fees)));
+            })
+            .assert_ok();
+    }
+
+    /// Sets the fees of a tag in the storage layout prior to per-token fees, without migrating them.
+    pub fn set_legacy_tag_fees(&mut self, tag: &[u8], tag_fees: u64) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.legacy_collected_tag_fees(&managed_buffer!(tag)).set(managed_biguint!(tag_fees));
+            })
+            .assert_ok();
+    }
+
+    /// Clears the reserve and migrates it, as after upgrading from the layout without reserve accounting.
+    pub fn migrate_reserve(&mut self, clear_reserve: bool, expected_err: Option<&str>) {
+        if clear_reserve {
+            self.b_wrapper
+                .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                    sc.reserve_amount(&sc.wrapped_token().get()).clear();
+                })
+                .assert_ok();
+        }
+
+        let tx = self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.migrate_reserve();
+            });
+
+        if let Some(msg) = expected_err {
+            tx.assert_error(4, msg);
+            return
+        }
+
+        tx.assert_ok()
+    }
+
+    pub fn swap_dust_token(
+        &mut self,
+        payments: &[TxTokenTransfer],
+        caller: &Address,
+        num_wegld: usize,
+        min_out_amount: u64,
+        expected_err: Option<&str>,
+        referral_tag: Option<&[u8]>
+    ) {
+        self.swap_dust_token_for_output(payments, caller, num_wegld, None, min_out_amount, expected_err, referral_tag)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_dust_token_for_output(
+        &mut self,
+        payments: &[TxTokenTransfer],
+        caller: &Address,
+        num_wegld: usize,
+        output_token: Option<&[u8]>,
+        min_out_amount: u64,
+        expected_err: Option<&str>,
+        referral_tag: Option<&[u8]>
+    ) {
+        let tx = self.b_wrapper
+            .execute_esdt_multi_transfer(caller, &self.c_wrapper, payments, |sc|{
+                let referral_tag_wrapped = match referral_tag {
+                    Some(tag) => OptionalValue::Some(managed_buffer!(tag)),
+                    None => OptionalValue::None
+                };
+                let output_token_id = match output_token {
+                    Some(b"EGLD") => EgldOrEsdtTokenIdentifier::egld(),
+                    Some(token) => EgldOrEsdtTokenIdentifier::esdt(managed_token_id!(token)),
+                    None => EgldOrEsdtTokenIdentifier::esdt(sc.wrapped_token().get())
+                };
+                sc.swap_dust_tokens(num_wegld, output_token_id, managed_biguint!(min_out_amount), referral_tag_wrapped);
+            });
+
+        if let Some(msg) = expected_err {
+            tx.assert_error(4, msg);
+            return
+        }
+
+        tx.assert_ok()
+    }
+
+    pub fn swap_dust_tokens_to(
+        &mut self,
+        payments: &[TxTokenTransfer],
+        caller: &Address,
+        num_wegld: usize,
+        destination: &Address,
+        refund_address: &Address,
+        referral_tag: Option<&[u8]>
+    ) {
+        self.b_wrapper
+            .execute_esdt_multi_transfer(caller, &self.c_wrapper, payments, |sc|{
+                let referral_tag_wrapped = match referral_tag {
+                    Some(tag) => OptionalValue::Some(managed_buffer!(tag)),
+                    None => OptionalValue::None
+                };
+                let output_token_id = EgldOrEsdtTokenIdentifier::esdt(sc.wrapped_token().get());
+                sc.swap_dust_tokens_to(
+                    num_wegld,
+                    output_token_id,
+                    managed_biguint!(0u64),
+                    managed_address!(destination),
+                    managed_address!(refund_address),
+                    referral_tag_wrapped
+                );
+            })
+            .assert_ok();
+    }
+
+    pub fn swap_dust_tokens_and_call(
+        &mut self,
+        payments: &[TxTokenTransfer],
+        caller: &Address,
+        target: &Address,
+        endpoint_name: &[u8],
+        args: Vec<Vec<u8>>,
+        expected_err: Option<&str>
+    ) {
+        let tx = self.b_wrapper
+            .execute_esdt_multi_transfer(caller, &self.c_wrapper, payments, |sc|{
+                let output_token_id = EgldOrEsdtTokenIdentifier::esdt(sc.wrapped_token().get());
+                let mut call_args = MultiValueEncoded::new();
+                for arg in args {
+                    call_args.push(managed_buffer!(&arg));
+                }
+
+                sc.swap_dust_tokens_and_call(
+                    payments.len(),
+                    output_token_id,
+                    managed_biguint!(0u64),
+                    managed_address!(target),
+                    managed_buffer!(endpoint_name),
+                    call_args
+                );
+            });
+
+        if let Some(msg) = expected_err {
+            tx.assert_error(4, msg);
+            return
+        }
+
+        tx.assert_ok()
+    }
+
+    pub fn add_call_target(&mut self, target: &Address, endpoint_name: &[u8]) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.add_call_target(managed_address!(target), managed_buffer!(endpoint_name));
+            })
+            .assert_ok();
+    }
+
+    pub fn check_pair_deposit(&mut self, owner: &Address, expected_amount: u64) {
+        self.b_wrapper
+            .execute_query(&self.pair_wrapper, |sc| {
+                assert_eq!(sc.deposits(&managed_address!(owner)).get(), managed_biguint!(expected_amount));
+            })
+            .assert_ok();
+    }
+
+    /// Swaps the payments to WEGLD and checks the tokens refunded and why.
+    pub fn swap_dust_token_with_refunds(
+        &mut self,
+        payments: &[TxTokenTransfer],
+        caller: &Address,
+        num_wegld: usize,
+        expected_refunds: Vec<(&[u8], u64, RefundReason)>
+    ) {
+        self.b_wrapper
+            .execute_esdt_multi_transfer(caller, &self.c_wrapper, payments, |sc|{
+                let output_token_id = EgldOrEsdtTokenIdentifier::esdt(sc.wrapped_token().get());
+                let result = sc.swap_dust_tokens(num_wegld, output_token_id, managed_biguint!(0u64), OptionalValue::None);
+                assert_eq!(result.refunds.len(), expected_refunds.len());
+
+                for ((token, amount, reason), refund) in expected_refunds.into_iter().zip(result.refunds.iter()) {
+                    assert_eq!(refund.payment.token_identifier, managed_token_id!(token));
+                    assert_eq!(refund.payment.amount, managed_biguint!(amount));
+                    assert_eq!(refund.reason, reason);
+                }
+            })
+            .assert_ok();
+    }
+
+    /// Swaps the payments to WEGLD and checks the result returned by the endpoint.
+    #[allow(clippy::too_many_arguments)]
+    pub fn check_swap_result(
+        &mut self,
+        payments: &[TxTokenTransfer],
+        caller: &Address,
+        num_wegld: usize,
+        referral_tag: Option<&[u8]>,
+        expected_amount_out: u64,
+        expected_protocol_fee: u64,
+        expected_referral_fee: u64,
+        expected_refunds: Vec<&[u8]>
+    ) {
+        self.b_wrapper
+            .execute_esdt_multi_transfer(caller, &self.c_wrapper, payments, |sc|{
+                let referral_tag_wrapped = match referral_tag {
+                    Some(tag) => OptionalValue::Some(managed_buffer!(tag)),
+                    None => OptionalValue::None
+                };
+                let output_token_id = EgldOrEsdtTokenIdentifier::esdt(sc.wrapped_token().get());
+                let result = sc.swap_dust_tokens(num_wegld, output_token_id.clone(), managed_biguint!(expected_amount_out), referral_tag_wrapped);
+                assert_eq!(result.output.token_identifier, output_token_id);
+                assert_eq!(result.output.amount, managed_biguint!(expected_amount_out));
+                assert_eq!(result.protocol_fee, managed_biguint!(expected_protocol_fee));
+                assert_eq!(result.referral_fee, managed_biguint!(expected_referral_fee));
+
+                assert_eq!(result.refunds.len(), expected_refunds.len());
+                for (refund, expected_token) in result.refunds.iter().zip(expected_refunds.iter()) {
+                    assert_eq!(refund.payment.token_identifier, managed_token_id!(*expected_token));
+                }
+            })
+            .assert_ok();
+    }
+
+    /// Swaps the payments to WEGLD and checks the topics and data of the swapDustTokens event.
+    pub fn check_swap_event(
+        &mut self,
+        payments: &[TxTokenTransfer],
+        caller: &Address,
+        num_wegld: usize,
+        expected_amount_out: u64,
+        expected_protocol_fee: u64,
+        expected_refunds: Vec<(&[u8], u64)>
+    ) {
+        self.b_wrapper
+            .execute_esdt_multi_transfer(caller, &self.c_wrapper, payments, |sc|{
+                let output_token_id = EgldOrEsdtTokenIdentifier::esdt(sc.wrapped_token().get());
+                sc.swap_dust_tokens(num_wegld, output_token_id.clone(), managed_biguint!(0u64), OptionalValue::None);
 
+                let mut event_payments = ManagedVec::new();
+                for payment in payments {
+                    event_payments.push(EsdtTokenPayment::new(
+                        managed_token_id!(payment.token_identifier.as_slice()),
+                        payment.nonce,
+                        BigUint::from_bytes_be(&payment.value.to_bytes_be())
+                    ));
                 }
-                sc.add_known_tokens(managed_token_id!(output_token), payload_tokens);
+                let mut refunds = ManagedVec::new();
+                for (token, amount) in expected_refunds {
+                    refunds.push(EsdtTokenPayment::new(managed_token_id!(token), 0, managed_biguint!(amount)));
+                }
+
+                let expected_data = top_encode_to_vec_u8_or_panic(&SwapDustTokensEvent {
+                    payments: event_payments,
+                    output_token: output_token_id,
+                    amount_out: managed_biguint!(expected_amount_out),
+                    protocol_fee: managed_biguint!(expected_protocol_fee),
+                    referral_tag: None,
+                    referral_fee: BigUint::zero(),
+                    refunds,
+                });
+                check_event(b"swapDustTokens", vec![caller.as_bytes()], &expected_data);
             })
             .assert_ok();
     }
 
-    pub fn remove_known_tokens(&mut self, output_token: &[u8], known_tokens: Vec<&[u8]>) {
+    pub fn set_refund_only_allowed(&mut self, allowed: bool) {
         self.b_wrapper
             .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
-                let mut payload_tokens = MultiValueEncoded::new();
-                for t in known_tokens {
-                    payload_tokens.push(managed_token_id!(t));
-
-                }
-                sc.remove_known_tokens(managed_token_id!(output_token), payload_tokens);
+                sc.set_refund_only_allowed(allowed);
+                check_event(b"refundOnlyAllowed", vec![], &top_encode_to_vec_u8_or_panic(&allowed));
             })
             .assert_ok();
     }
 
-    pub fn swap_dust_token(
+    pub fn swap_dust_tokens_any_order(&mut self, payments: &[TxTokenTransfer], caller: &Address, min_out_amount: u64, expected_err: Option<&str>) {
+        let tx = self.b_wrapper
+            .execute_esdt_multi_transfer(caller, &self.c_wrapper, payments, |sc|{
+                let output_token_id = EgldOrEsdtTokenIdentifier::esdt(sc.wrapped_token().get());
+                sc.swap_dust_tokens_any_order(output_token_id, managed_biguint!(min_out_amount), OptionalValue::None);
+            });
+
+        if let Some(msg) = expected_err {
+            tx.assert_error(4, msg);
+            return
+        }
+
+        tx.assert_ok()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn check_swap_quote(
         &mut self,
         payments: &[TxTokenTransfer],
-        caller: &Address,
         num_wegld: usize,
-        min_out_amount: u64,
-        expected_err: Option<&str>,
-        referral_tag: Option<&[u8]>
+        referral_tag: Option<&[u8]>,
+        expected_amount_out: u64,
+        expected_protocol_fee: u64,
+        expected_referral_fee: u64,
+        expected_refunds: Vec<&[u8]>
     ) {
-        let tx = self.b_wrapper
-            .execute_esdt_multi_transfer(caller, &self.c_wrapper, payments, |sc|{
+        self.b_wrapper
+            .execute_query(&self.c_wrapper, |sc| {
+                let mut managed_payments = ManagedVec::new();
+                for p in payments {
+                    managed_payments.push(EsdtTokenPayment::new(
+                        managed_token_id!(p.token_identifier.clone()),
+                        0,
+                        BigUint::from_bytes_be(&p.value.to_bytes_be())
+                    ));
+                }
                 let referral_tag_wrapped = match referral_tag {
                     Some(tag) => OptionalValue::Some(managed_buffer!(tag)),
                     None => OptionalValue::None
                 };
-                sc.swap_dust_tokens(num_wegld, managed_biguint!(min_out_amount), referral_tag_wrapped);
+
+                let quote = sc.get_swap_quote(
+                    num_wegld,
+                    EgldOrEsdtTokenIdentifier::esdt(sc.wrapped_token().get()),
+                    managed_payments,
+                    referral_tag_wrapped
+                );
+                assert_eq!(quote.amount_out, managed_biguint!(expected_amount_out));
+                assert_eq!(quote.protocol_fee, managed_biguint!(expected_protocol_fee));
+                assert_eq!(quote.referral_fee, managed_biguint!(expected_referral_fee));
+
+                assert_eq!(quote.refunds.len(), expected_refunds.len());
+                for (refund, expected_token) in quote.refunds.iter().zip(expected_refunds.iter()) {
+                    assert_eq!(refund.payment.token_identifier, managed_token_id!(*expected_token));
+                }
+            })
+            .assert_ok();
+    }
+
+    pub fn sell_dust_token(&mut self, tokens: Vec<&[u8]>) {
+        let owner = self.owner.clone();
+        self.sell_dust_token_from(&owner, tokens, None);
+    }
+
+    pub fn sell_dust_token_from(&mut self, caller: &Address, tokens: Vec<&[u8]>, expected_err: Option<&str>) {
+        let tx = self.b_wrapper
+            .execute_tx(caller, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                let mut multi = MultiValueEncoded::new();
+                for token in tokens {
+                    multi.push(managed_token_id!(token));
+                }
+
+                sc.sell_dust_tokens(multi);
             });
 
         if let Some(msg) = expected_err {
@@ -181,15 +774,65 @@ where
         tx.assert_ok()
     }
 
-    pub fn sell_dust_token(&mut self, tokens: Vec<&[u8]>) {
+    pub fn sell_dust_tokens_batch(&mut self, expected_sold: Vec<&[u8]>, expected_finished: bool) {
         self.b_wrapper
             .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
-                let mut multi = MultiValueEncoded::new();
-                for token in tokens {
-                    multi.push(managed_token_id!(token));
+                let summary = sc.sell_dust_tokens_batch();
+
+                assert_eq!(summary.finished, expected_finished);
+                assert_eq!(summary.sold.len(), expected_sold.len());
+                for (sold, expected_token) in summary.sold.iter().zip(expected_sold.iter()) {
+                    assert_eq!(sold.token_id, managed_token_id!(*expected_token));
                 }
+            })
+            .assert_ok();
+    }
+
+    /// Moves the batch cursor as if a previous batch had stopped there.
+    pub fn set_sell_cursor(&mut self, output_index: usize, token_index: usize) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.sell_cursor().set(SellCursor { output_index, token_index });
+            })
+            .assert_ok();
+    }
+
+    pub fn set_sell_min_gas_left(&mut self, min_gas_left: u64) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_sell_min_gas_left(min_gas_left);
+            })
+            .assert_ok();
+    }
+
+    pub fn setup_keeper_selling(&mut self, keeper: &Address, bounty_percent: u64, max_bounty_token: &[u8], max_bounty: u64) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                let mut keepers = MultiValueEncoded::new();
+                keepers.push(managed_address!(keeper));
+                sc.add_keepers(keepers);
+                sc.set_keeper_only_selling(true);
+                sc.set_keeper_bounty_percentage(bounty_percent);
+                sc.set_max_keeper_bounty(managed_token_id!(max_bounty_token), managed_biguint!(max_bounty));
+            })
+            .assert_ok();
+    }
 
+    /// Sells the token and checks the topics and data of the sellDustToken event.
+    pub fn check_sell_event(&mut self, token: &[u8], expected_amount_in: u64, expected_proceeds: u64, expected_keeper_bounty: u64) {
+        let owner = self.owner.clone();
+        self.b_wrapper
+            .execute_tx(&owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                let mut multi = MultiValueEncoded::new();
+                multi.push(managed_token_id!(token));
                 sc.sell_dust_tokens(multi);
+
+                let expected_data = top_encode_to_vec_u8_or_panic(&SellDustTokenEvent {
+                    amount_in: managed_biguint!(expected_amount_in),
+                    proceeds: EsdtTokenPayment::new(sc.wrapped_token().get(), 0, managed_biguint!(expected_proceeds)),
+                    keeper_bounty: managed_biguint!(expected_keeper_bounty),
+                });
+                check_event(b"sellDustToken", vec![owner.as_bytes(), token], &expected_data);
             })
             .assert_ok();
     }
@@ -256,6 +899,33 @@ where
         tx.assert_ok()
     }
 
+    /// Claims the tag owner's WEGLD referral fees and checks the topics and data of the claimReferralFees event.
+    pub fn check_claim_referral_fees_event(&mut self, caller: &Address, tag: &[u8], expected_fee: u64) {
+        self.b_wrapper
+            .execute_tx(caller, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.claim_referral_fees(OptionalValue::None);
+
+                let mut fees = ManagedVec::<DebugApi, EsdtTokenPayment<DebugApi>>::new();
+                fees.push(EsdtTokenPayment::new(sc.wrapped_token().get(), 0, managed_biguint!(expected_fee)));
+                check_event(b"claimReferralFees", vec![caller.as_bytes(), tag], &top_encode_to_vec_u8_or_panic(&fees));
+            })
+            .assert_ok();
+    }
+
+    /// Extracts the WEGLD protocol fees and checks the topics and data of the extractFees event.
+    pub fn check_extract_fees_event(&mut self, expected_fee: u64) {
+        let owner = self.owner.clone();
+        self.b_wrapper
+            .execute_tx(&owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.extract_fees(OptionalValue::None);
+
+                let mut fees = ManagedVec::<DebugApi, EsdtTokenPayment<DebugApi>>::new();
+                fees.push(EsdtTokenPayment::new(sc.wrapped_token().get(), 0, managed_biguint!(expected_fee)));
+                check_event(b"extractFees", vec![owner.as_bytes()], &top_encode_to_vec_u8_or_panic(&fees));
+            })
+            .assert_ok();
+    }
+
     pub fn update_tier(
         &mut self,
         user: &Address,
@@ -318,12 +988,238 @@ where
     pub fn check_referral_fee_amount(&mut self, tag: &[u8], expected_amount: u64) {
         self.b_wrapper
             .execute_query(&self.c_wrapper, |sc| {
-                let amount = sc.collected_tag_fees(&managed_buffer!(tag)).get();
+                let amount = sc.collected_tag_fees(&managed_buffer!(tag), &sc.wrapped_token().get()).get();
+                assert_eq!(amount, managed_biguint!(expected_amount));
+            })
+            .assert_ok();
+    }
+
+    pub fn check_protocol_fee_amount(&mut self, token: &[u8], expected_amount: u64) {
+        self.b_wrapper
+            .execute_query(&self.c_wrapper, |sc| {
+                let amount = sc.collected_fee_amount(&managed_token_id!(token)).get();
                 assert_eq!(amount, managed_biguint!(expected_amount));
             })
             .assert_ok();
     }
 
+    pub fn check_reserve_status(&mut self, token: &[u8], expected_reserve: u64, expected_protocol_fees: u64, expected_referral_fees: u64) {
+        self.b_wrapper
+            .execute_query(&self.c_wrapper, |sc| {
+                let (reserve, protocol_fees, referral_fees) = sc.get_reserve_status(managed_token_id!(token)).into_tuple();
+                assert_eq!(reserve, BigUint::from_bytes_be(&rust_biguint!(expected_reserve).to_bytes_be()));
+                assert_eq!(protocol_fees, managed_biguint!(expected_protocol_fees));
+                assert_eq!(referral_fees, managed_biguint!(expected_referral_fees));
+            })
+            .assert_ok();
+    }
+
+    pub fn add_output_token(&mut self, output_token: &[u8], hops: Vec<&[u8]>) {
+        let mut wrapped_token = Vec::new();
+        self.b_wrapper
+            .execute_query(&self.c_wrapper, |sc| {
+                wrapped_token = sc.wrapped_token().get().as_managed_buffer().to_boxed_bytes().into_vec();
+            })
+            .assert_ok();
+
+        let pairs = self.create_route_pairs(&wrapped_token, &hops);
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                let mut route = ManagedVec::new();
+                for (hop, pair) in hops.iter().zip(pairs.iter()) {
+                    route.push(PairContractData {
+                        address: managed_address!(pair),
+                        output_token: managed_token_id!(*hop)
+                    });
+                }
+
+                sc.add_output_token(managed_token_id!(output_token), route);
+            })
+            .assert_ok();
+    }
+
+    pub fn add_base_tokens(&mut self, tokens: Vec<&[u8]>, expected_err: Option<&str>) {
+        let tx = self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                let mut multi = MultiValueEncoded::new();
+                for token in tokens {
+                    multi.push(managed_token_id!(token));
+                }
+
+                sc.add_base_tokens(multi);
+            });
+
+        if let Some(msg) = expected_err {
+            tx.assert_error(4, msg);
+            return
+        }
+
+        tx.assert_ok()
+    }
+
+    pub fn set_atomic_swap_mode(&mut self, enabled: bool) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_atomic_swap_mode(enabled);
+            })
+            .assert_ok();
+    }
+
+    pub fn set_max_price_deviation(&mut self, token: &[u8], max_deviation: u64) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_max_price_deviation(managed_token_id!(token), max_deviation);
+                check_event(b"maxPriceDeviation", vec![token], &top_encode_to_vec_u8_or_panic(&max_deviation));
+            })
+            .assert_ok();
+    }
+
+    pub fn set_min_quote(&mut self, token: &[u8], min_quote: u64) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_min_quote(managed_token_id!(token), managed_biguint!(min_quote));
+            })
+            .assert_ok();
+    }
+
+    pub fn set_token_caps(&mut self, token: &[u8], max_swap_value: u64, epoch_inflow_cap: u64) {
+        self.set_token_caps_with_err(token, max_swap_value, epoch_inflow_cap, None);
+    }
+
+    pub fn set_token_caps_with_err(&mut self, token: &[u8], max_swap_value: u64, epoch_inflow_cap: u64, expected_err: Option<&str>) {
+        let tx = self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_token_caps(managed_token_id!(token), managed_biguint!(max_swap_value), managed_biguint!(epoch_inflow_cap));
+            });
+
+        if let Some(msg) = expected_err {
+            tx.assert_error(4, msg);
+            return
+        }
+
+        tx.assert_ok()
+    }
+
+    pub fn check_remaining_epoch_inflow(&mut self, token: &[u8], expected_remaining: u64) {
+        self.b_wrapper
+            .execute_query(&self.c_wrapper, |sc| {
+                let (_, _, remaining) = sc.get_token_cap_status(managed_token_id!(token)).into_tuple();
+                assert_eq!(remaining, managed_biguint!(expected_remaining));
+            })
+            .assert_ok();
+    }
+
+    pub fn add_pair_candidates(&mut self, token: &[u8], pair_addresses: Vec<&Address>) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                let mut addresses = MultiValueEncoded::new();
+                for address in pair_addresses {
+                    addresses.push(managed_address!(address));
+                }
+
+                sc.add_pair_candidates(managed_token_id!(token), addresses);
+            })
+            .assert_ok();
+    }
+
+    pub fn set_execution_policy(&mut self, token: &[u8], max_chunk_amount: u64, min_rounds_between_chunks: u64) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_execution_policy(
+                    managed_token_id!(token),
+                    managed_biguint!(max_chunk_amount),
+                    managed_biguint!(0u64),
+                    0u64,
+                    min_rounds_between_chunks
+                );
+            })
+            .assert_ok();
+    }
+
+    pub fn check_sell_progress(&mut self, token: &[u8], expected_remaining: u64, expected_chunks: u64, expected_next_round: u64) {
+        self.b_wrapper
+            .execute_query(&self.c_wrapper, |sc| {
+                let progress = sc.get_sell_progress(managed_token_id!(token));
+                assert_eq!(progress.remaining_amount, managed_biguint!(expected_remaining));
+                assert_eq!(progress.chunks_sold, expected_chunks);
+                assert_eq!(progress.next_sell_round, expected_next_round);
+            })
+            .assert_ok();
+    }
+
+    pub fn set_sell_loss_tolerance(&mut self, tolerance: u64) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_sell_loss_tolerance(tolerance);
+                check_event(b"sellLossTolerance", vec![], &top_encode_to_vec_u8_or_panic(&tolerance));
+            })
+            .assert_ok();
+    }
+
+    pub fn check_token_pnl(&mut self, token: &[u8], expected_quantity: u64, expected_cost: u64, expected_realized: i64, expected_unrealized: i64) {
+        self.b_wrapper
+            .execute_query(&self.c_wrapper, |sc| {
+                let (quantity, cost, realized, unrealized) = sc.get_token_pnl(managed_token_id!(token)).into_tuple();
+                assert_eq!(quantity, managed_biguint!(expected_quantity));
+                assert_eq!(cost, managed_biguint!(expected_cost));
+                assert_eq!(realized, BigInt::from(expected_realized));
+                assert_eq!(unrealized, BigInt::from(expected_unrealized));
+            })
+            .assert_ok();
+    }
+
+    pub fn set_pair_price_multiplier(&mut self, rate: u64) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.pair_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_price_multiplier(rate);
+            })
+            .assert_ok();
+    }
+
+    pub fn set_safe_price_rate(&mut self, token: &[u8], rate: u64) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.pair_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_safe_price_rate(managed_token_id!(token), rate);
+            })
+            .assert_ok();
+    }
+
+    pub fn set_forward_base_proceeds(&mut self, enabled: bool) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_forward_base_proceeds(enabled);
+                check_event(b"forwardBaseProceeds", vec![], &top_encode_to_vec_u8_or_panic(&enabled));
+            })
+            .assert_ok();
+    }
+
+    pub fn set_wrapping_contract(&mut self, address: &Address) {
+        self.b_wrapper
+            .execute_tx(&self.owner, &self.c_wrapper, &rust_biguint!(0u64), |sc| {
+                sc.set_wrapping_contract(managed_address!(address));
+            })
+            .assert_ok();
+    }
+
+    pub fn top_up(&mut self, token: &[u8], amount: u64) {
+        self.b_wrapper.set_esdt_balance(&self.owner, token, &rust_biguint!(amount));
+        self.b_wrapper
+            .execute_esdt_transfer(&self.owner, &self.c_wrapper, token, 0, &rust_biguint!(amount), |sc| {
+                sc.top_up();
+            })
+            .assert_ok();
+    }
+
+    pub fn check_base_tokens(&mut self, expected_tokens: Vec<&[u8]>) {
+        self.b_wrapper
+            .execute_query(&self.c_wrapper, |sc| {
+                let base_tokens: Vec<TokenIdentifier<DebugApi>> = sc.base_tokens().iter().collect();
+                let expected_tokens: Vec<TokenIdentifier<DebugApi>> = expected_tokens.into_iter().map(|token| managed_token_id!(token)).collect();
+                assert_eq!(base_tokens, expected_tokens);
+            })
+            .assert_ok();
+    }
+
     pub fn check_all_tokens(&mut self, output_token: &[u8], expected_tokens: Vec<&[u8]>) {
         self.b_wrapper
             .execute_query(&self.c_wrapper, |sc| {
@@ -338,5 +1234,53 @@ where
             })
             .assert_ok();
     }
+
+    pub fn check_token_configs(&mut self, output_token: &[u8], from: usize, size: usize, expected_configs: Vec<(&[u8], bool, u64)>) {
+        self.b_wrapper
+            .execute_query(&self.c_wrapper, |sc| {
+                let configs: Vec<_> = sc.get_token_configs(managed_token_id!(output_token), from, size).into_iter().collect();
+                assert_eq!(configs.len(), expected_configs.len());
+
+                for ((token, enabled, threshold), entry) in expected_configs.into_iter().zip(configs) {
+                    let (token_id, config) = entry.into_tuple();
+                    assert_eq!(token_id, managed_token_id!(token));
+                    assert_eq!(config.enabled, enabled);
+                    assert_eq!(config.output_token, managed_token_id!(output_token));
+                    assert_eq!(config.threshold, managed_biguint!(threshold));
+                }
+            })
+            .assert_ok();
+    }
+}
+
+/// Checks the indexed topics and the data of the event the running transaction emitted with the given identifier.
+/// Must be called from inside the transaction closure, the testing framework drops the logs once it returns.
+pub fn check_event(identifier: &[u8], expected_topics: Vec<&[u8]>, expected_data: &[u8]) {
+    let tx_result = DebugApi::new_from_static().result_borrow_mut().clone();
+    let log = tx_result.result_logs
+        .iter()
+        .find(|log| log.topics[0] == identifier)
+        .unwrap_or_else(|| panic!("Event {} not emitted", String::from_utf8_lossy(identifier)));
+
+    let expected_topics: Vec<Vec<u8>> = expected_topics.into_iter().map(|topic| topic.to_vec()).collect();
+    assert_eq!(log.topics[1..], expected_topics[..]);
+    assert_eq!(log.data, expected_data);
 }
 
+/// Makes a pair mock report token_in and token_out as the tokens it trades.
+pub fn set_mock_pair_tokens<MockBuilder>(
+    b_wrapper: &mut BlockchainStateWrapper,
+    owner: &Address,
+    pair_wrapper: &ContractObjWrapper<pair_mock::ContractObj<DebugApi>, MockBuilder>,
+    token_in: &[u8],
+    token_out: &[u8]
+)
+where
+    MockBuilder: 'static + Copy + Fn() -> pair_mock::ContractObj<DebugApi>,
+{
+    b_wrapper
+        .execute_tx(owner, pair_wrapper, &rust_biguint!(0u64), |sc| {
+            sc.set_pair_tokens(managed_token_id!(token_in), managed_token_id!(token_out));
+        })
+        .assert_ok();
+}
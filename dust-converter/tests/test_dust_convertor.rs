@@ -1,8 +1,8 @@
 mod contract_interactions;
 use contract_interactions::*;
-use dust_converter::{self, config::{MAX_PERCENTAGE}};
+use dust_converter::{self, config::{MAX_PERCENTAGE}, quote::RefundReason, registry::RegistryIssueKind};
 use elrond_wasm_debug::{rust_biguint, tx_mock::TxTokenTransfer};
-use pair_mock::{self, ERR_TOKEN, AMOUNT_OUT, KNOWN_TOKEN_1, KNOWN_TOKEN_2, KNOWN_TOKEN_3, KNOWN_TOKEN_4, KNOWN_TOKEN_5};
+use pair_mock::{self, PairMock, State, ERR_TOKEN, AMOUNT_OUT, KNOWN_TOKEN_1, KNOWN_TOKEN_2, KNOWN_TOKEN_3, KNOWN_TOKEN_4, KNOWN_TOKEN_5};
 
 static WRAPPED_TOKEN: &[u8] = b"WEGLD-0a3f5r";
 static USDC_TOKEN: &[u8] = b"USDC-0a3f5r";
@@ -14,6 +14,11 @@ pub const TOKEN_3_RATE_PERCENTAGE: u64 = 150; //   1000 TOKEN3 = 15 TOKEN_OUT
 pub const TOKEN_4_RATE_PERCENTAGE: u64 = 2_300; // 1000 TOKEN4 = 230 TOKEN_OUT
 pub const TOKEN_5_RATE_PERCENTAGE: u64 = 4_670; // 1000 TOKEN5 = 467 TOKEN_OUT
 
+// the pair mock returns exactly the minimum amount out asked by the converter
+fn apply_slippage(amount: u64) -> u64 {
+    amount - amount * 50u64 / MAX_PERCENTAGE
+}
+
 fn compute_token_out_amount(token_in_amount: u64, token_id: &[u8]) -> u64 {
     match token_id {
         KNOWN_TOKEN_1 => token_in_amount * TOKEN_1_RATE_PERCENTAGE / MAX_PERCENTAGE,
@@ -241,11 +246,22 @@ fn test_swap_dust_tokens_usdc_wegld_refund_tokens () {
 #[test]
 fn test_swap_dust_token_pair_fail() {
     let initial_err_amount = 1_000_000u64;
+    let token_2_amount = 3_000_000u64;
+    let token_3_amount = 2_000_000u64;
     let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    let other_pair = setup.b_wrapper.create_sc_account(&rust_biguint!(0u64), Some(&setup.owner), pair_mock::contract_obj, "mocked wasm");
+    set_mock_pair_tokens(&mut setup.b_wrapper, &setup.owner, &other_pair, KNOWN_TOKEN_2, WRAPPED_TOKEN);
+    setup.b_wrapper.set_esdt_balance(other_pair.address_ref(), WRAPPED_TOKEN, &rust_biguint!(INITIAL_SC_BALANCE));
+    setup.add_known_token_with_pair(WRAPPED_TOKEN, KNOWN_TOKEN_2, Some(other_pair.address_ref()), None);
+    let empty_pair = setup.create_empty_pair(KNOWN_TOKEN_3, WRAPPED_TOKEN);
+    setup.add_known_token_with_pair(WRAPPED_TOKEN, KNOWN_TOKEN_3, Some(&empty_pair), None);
+    // registered last, so the default pair mock trades ERR_TOKEN and its getAmountOut panics
     setup.add_known_tokens(WRAPPED_TOKEN, vec![ERR_TOKEN]);
     setup.resume();
 
     setup.b_wrapper.set_esdt_balance(&setup.owner, ERR_TOKEN, &rust_biguint!(initial_err_amount));
+    setup.b_wrapper.set_esdt_balance(&setup.owner, KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+    setup.b_wrapper.set_esdt_balance(&setup.owner, KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
 
     let payments = [
         TxTokenTransfer {
@@ -253,11 +269,151 @@ fn test_swap_dust_token_pair_fail() {
             nonce: 0,
             value: rust_biguint!(initial_err_amount)
         },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_2_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_3_amount)
+        },
     ];
+
+    // the pairs without reserves are not quoted, their tokens are refunded and the other token still settles
+    let amount_out = compute_token_out_amount(token_2_amount, KNOWN_TOKEN_2);
+    let total = amount_out - amount_out * 500u64 / MAX_PERCENTAGE;
     let caller_address = setup.owner.clone();
-    setup.swap_dust_token(&payments, &caller_address, payments.len(), initial_err_amount, Some("Not enough reserve"), None);
+    setup.swap_dust_token(&payments, &caller_address, payments.len(), total, None, None);
 
     setup.b_wrapper.check_esdt_balance(&setup.owner, ERR_TOKEN, &rust_biguint!(initial_err_amount));
+    setup.b_wrapper.check_esdt_balance(&setup.owner, KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
+    setup.b_wrapper.check_esdt_balance(&setup.owner, KNOWN_TOKEN_2, &rust_biguint!(0u64));
+    setup.b_wrapper.check_esdt_balance(&setup.owner, WRAPPED_TOKEN, &rust_biguint!(total));
+}
+
+#[test]
+fn test_swap_dust_token_quote_over_pair_reserve() {
+    let token_2_amount = 3_000_000u64;
+    let token_3_amount = 2_000_000u64;
+    let small_token_3_amount = 100_000u64;
+    let wegld_reserve = 10_000u64;
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2]);
+    // the pair holds reserves, but its getAmountOut fails for amounts it cannot pay
+    let small_pair = setup.create_pair_with_reserves(KNOWN_TOKEN_3, WRAPPED_TOKEN, 1_000_000_000_000u64, wegld_reserve);
+    setup.add_known_token_with_pair(WRAPPED_TOKEN, KNOWN_TOKEN_3, Some(&small_pair), None);
+    setup.resume();
+    assert!(compute_token_out_amount(token_3_amount, KNOWN_TOKEN_3) >= wegld_reserve);
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_3, &rust_biguint!(token_3_amount + small_token_3_amount));
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_2_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_3_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(small_token_3_amount)
+        }
+    ];
+
+    // only the payment the pair can pay is quoted, the other one is refunded and the rest still settles
+    setup.swap_dust_token_with_refunds(&payments, &user, payments.len(), vec![
+        (KNOWN_TOKEN_3, token_3_amount, RefundReason::PairUnavailable),
+    ]);
+    let amount_out = compute_token_out_amount(token_2_amount, KNOWN_TOKEN_2) + compute_token_out_amount(small_token_3_amount, KNOWN_TOKEN_3);
+    let total = amount_out - amount_out * 500u64 / MAX_PERCENTAGE;
+    setup.b_wrapper.check_esdt_balance(&user, KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
+    setup.b_wrapper.check_esdt_balance(&user, WRAPPED_TOKEN, &rust_biguint!(total));
+}
+
+#[test]
+fn test_swap_dust_tokens_below_min_quote() {
+    let token_2_amount = 3_000_000u64;
+    let token_3_amount = 2_000_000u64;
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2, KNOWN_TOKEN_3]);
+    setup.resume();
+
+    let token_3_value = compute_token_out_amount(token_3_amount, KNOWN_TOKEN_3);
+    setup.set_min_quote(KNOWN_TOKEN_3, token_3_value + 1);
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_2_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_3_amount)
+        }
+    ];
+
+    setup.swap_dust_token_with_refunds(&payments, &user, payments.len(), vec![
+        (KNOWN_TOKEN_3, token_3_amount, RefundReason::BelowMinQuote),
+    ]);
+    setup.b_wrapper.check_esdt_balance(&user, KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
+
+    // a payment quoting exactly the minimum is accepted
+    setup.set_min_quote(KNOWN_TOKEN_3, token_3_value);
+    setup.swap_dust_token_with_refunds(&payments[1..], &user, 1, vec![]);
+    setup.b_wrapper.check_esdt_balance(&user, KNOWN_TOKEN_3, &rust_biguint!(0u64));
+}
+
+#[test]
+fn test_swap_dust_tokens_refund_only() {
+    let unknown_token_amount = 2_500_000u64;
+    let token_2_amount = 100u64;
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2]);
+    setup.resume();
+
+    setup.b_wrapper.set_esdt_balance(&setup.owner, UNKOWN_TOKEN_3, &rust_biguint!(unknown_token_amount));
+    setup.b_wrapper.set_esdt_balance(&setup.owner, KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+
+    // the known token amount is too small to quote to anything
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: UNKOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(unknown_token_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_2_amount)
+        },
+    ];
+    let caller_address = setup.owner.clone();
+    setup.swap_dust_token(&payments, &caller_address, payments.len(), 0, Some("Zero amount cannot be claimed"), None);
+
+    setup.set_refund_only_allowed(true);
+    // a refund cannot satisfy a minimum output
+    setup.swap_dust_token(&payments, &caller_address, payments.len(), 1, Some("Slippage exceeded"), None);
+    setup.swap_dust_token_with_refunds(&payments, &caller_address, payments.len(), vec![
+        (UNKOWN_TOKEN_3, unknown_token_amount, RefundReason::UnknownToken),
+        (KNOWN_TOKEN_2, token_2_amount, RefundReason::ZeroQuote),
+    ]);
+
+    setup.b_wrapper.check_esdt_balance(&setup.owner, UNKOWN_TOKEN_3, &rust_biguint!(unknown_token_amount));
+    setup.b_wrapper.check_esdt_balance(&setup.owner, KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+    setup.b_wrapper.check_esdt_balance(&setup.owner, WRAPPED_TOKEN, &rust_biguint!(0u64));
 }
 
 #[test]
@@ -299,207 +455,1177 @@ fn test_sell_dust_tokens() {
 
 
 #[test]
-fn test_refund_unknown_tokens() {
-    let known_token_amount = 3_000_000u64;
-    let unkown_token_amount = 2_500_000u64;
-
+fn test_swap_and_sell_dust_tokens_multi_hop_route() {
+    let token_4_amount = 3_000_000u64;
     let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
-    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.add_known_token_with_route(WRAPPED_TOKEN, KNOWN_TOKEN_4, vec![KNOWN_TOKEN_3, WRAPPED_TOKEN]);
     setup.resume();
 
     let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
-
-    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_1, &rust_biguint!(known_token_amount));
-    setup.b_wrapper.set_esdt_balance(&user, UNKOWN_TOKEN_3, &rust_biguint!(unkown_token_amount));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_4, &rust_biguint!(token_4_amount));
 
     let payments = [
         TxTokenTransfer {
-            token_identifier: KNOWN_TOKEN_1.to_vec(),
-            nonce: 0,
-            value: rust_biguint!(known_token_amount)
-        },
-        TxTokenTransfer {
-            token_identifier: UNKOWN_TOKEN_3.to_vec(),
+            token_identifier: KNOWN_TOKEN_4.to_vec(),
             nonce: 0,
-            value: rust_biguint!(unkown_token_amount)
+            value: rust_biguint!(token_4_amount)
         }
     ];
 
-    let amount_out = compute_token_out_amount(known_token_amount, KNOWN_TOKEN_1);
+    let intermediate_amount = compute_token_out_amount(token_4_amount, KNOWN_TOKEN_4);
+    let amount_out = compute_token_out_amount(intermediate_amount, KNOWN_TOKEN_3);
     let fee = amount_out * 500u64 / MAX_PERCENTAGE;
     let total = amount_out - fee;
 
     setup.swap_dust_token(&payments, &user, payments.len(), total, None, None);
+    setup.b_wrapper.check_esdt_balance(&user, WRAPPED_TOKEN, &rust_biguint!(total));
 
-    setup.b_wrapper.check_esdt_balance(&user, KNOWN_TOKEN_1, &rust_biguint!(0u64));
-    setup.b_wrapper.check_esdt_balance(&user, UNKOWN_TOKEN_3, &rust_biguint!(unkown_token_amount));
+    setup.b_wrapper.set_esdt_balance(setup.pair_wrapper.address_ref(), KNOWN_TOKEN_3, &rust_biguint!(intermediate_amount));
+    setup.sell_dust_token(vec![KNOWN_TOKEN_4]);
+
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_4, &rust_biguint!(0u64));
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_3, &rust_biguint!(0u64));
 }
 
 #[test]
-fn test_register_referral_tag() {
+fn test_add_known_tokens_through_router() {
     let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
-    setup.resume();
+    let pair = setup.pair_wrapper.address_ref().clone();
 
-    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
-    let tag = "TEST5".to_string();
-    setup.register_referral_tag(&user, tag.as_bytes());
-    setup.check_registered_tags(tag.as_bytes(), &user);
-    setup.check_referral_fee_percentage(TIER_1_FEE_PERCENT, tag.as_bytes());
+    setup.add_known_token_with_pair(WRAPPED_TOKEN, KNOWN_TOKEN_2, None, Some("Router not set"));
+
+    set_mock_pair_tokens(&mut setup.b_wrapper, &setup.owner, &setup.pair_wrapper, KNOWN_TOKEN_3, WRAPPED_TOKEN);
+    setup.add_known_token_with_pair(WRAPPED_TOKEN, KNOWN_TOKEN_2, Some(&pair), Some("Pair does not trade route tokens"));
+
+    // the pair's tokens can be in either order
+    set_mock_pair_tokens(&mut setup.b_wrapper, &setup.owner, &setup.pair_wrapper, WRAPPED_TOKEN, KNOWN_TOKEN_2);
+    setup.set_pair_state(State::Inactive);
+    setup.add_known_token_with_pair(WRAPPED_TOKEN, KNOWN_TOKEN_2, Some(&pair), Some("Pair not active"));
+    setup.set_pair_state(State::Active);
+
+    setup.set_router_pairs(vec![(WRAPPED_TOKEN, KNOWN_TOKEN_2)]);
+    setup.add_known_token_with_pair(WRAPPED_TOKEN, KNOWN_TOKEN_3, None, Some("Pair not found"));
+    setup.add_known_token_with_pair(WRAPPED_TOKEN, KNOWN_TOKEN_2, None, None);
+    setup.check_token_route(KNOWN_TOKEN_2, vec![&pair]);
 }
 
 #[test]
-fn test_update_referral_tag_percent() {
+fn test_sell_dust_tokens_in_chunks() {
+    let token_2_amount = 4_000_000u64;
+    let chunk_amount = 1_500_000u64;
     let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2]);
     setup.resume();
-
-    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
-    let tag = "TEST5".to_string();
-    setup.register_referral_tag(&user, tag.as_bytes());
-
-    setup.check_referral_fee_percentage(500u64, tag.as_bytes());
-    setup.set_referral_fee_percentage(1000u64, tag.as_bytes());
-    setup.check_referral_fee_percentage(1000u64, tag.as_bytes());
+    setup.set_execution_policy(KNOWN_TOKEN_2, chunk_amount, 10u64);
+    setup.b_wrapper.set_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+
+    setup.b_wrapper.set_block_round(5u64);
+    setup.sell_dust_token(vec![KNOWN_TOKEN_2]);
+    setup.check_sell_progress(KNOWN_TOKEN_2, token_2_amount - chunk_amount, 1u64, 15u64);
+
+    // the next chunk is not due yet
+    setup.sell_dust_token(vec![KNOWN_TOKEN_2]);
+    setup.check_sell_progress(KNOWN_TOKEN_2, token_2_amount - chunk_amount, 1u64, 15u64);
+
+    setup.b_wrapper.set_block_round(15u64);
+    setup.sell_dust_token(vec![KNOWN_TOKEN_2]);
+    setup.b_wrapper.set_block_round(25u64);
+    setup.sell_dust_token(vec![KNOWN_TOKEN_2]);
+    setup.check_sell_progress(KNOWN_TOKEN_2, 0u64, 3u64, 35u64);
 }
 
 #[test]
-fn test_swap_token_with_referral_tag() {
-    let known_token_amount_1 = 3_000_000u64;
-    let known_token_amount_2 = 2_500_000u64;
-    let tag = b"TEST5";
-
+fn test_sell_dust_tokens_above_cost_basis() {
+    let token_2_amount = 4_000_000u64;
     let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
-    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2]);
     setup.resume();
-    let user_1 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
-    setup.register_referral_tag(&user_1, tag);
+    setup.set_sell_loss_tolerance(100u64);
 
-    let user_2 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
-    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_1, &rust_biguint!(known_token_amount_1));
-    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_2, &rust_biguint!(known_token_amount_2));
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
     let payments = [
-        TxTokenTransfer {
-            token_identifier: KNOWN_TOKEN_1.to_vec(),
-            nonce: 0,
-            value: rust_biguint!(known_token_amount_1)
-        },
         TxTokenTransfer {
             token_identifier: KNOWN_TOKEN_2.to_vec(),
             nonce: 0,
-            value: rust_biguint!(known_token_amount_2)
+            value: rust_biguint!(token_2_amount)
         }
     ];
+    setup.swap_dust_token(&payments, &user, payments.len(), 0u64, None, None);
 
-    let amount_out = compute_token_out_amount(known_token_amount_1, KNOWN_TOKEN_1) + compute_token_out_amount(known_token_amount_2, KNOWN_TOKEN_2);
-    let fee = amount_out * TIER_1_FEE_PERCENT / MAX_PERCENTAGE;
-    let referral_fee = fee * TIER_1_FEE_PERCENT / MAX_PERCENTAGE;
-    let total = amount_out - fee;
-    setup.swap_dust_token(&payments, &user_2, payments.len(), total, None, Some(tag));
-    setup.check_referral_fee_amount(tag, referral_fee);
+    let cost = compute_token_out_amount(token_2_amount, KNOWN_TOKEN_2);
+    setup.check_token_pnl(KNOWN_TOKEN_2, token_2_amount, cost, 0i64, 0i64);
 
-    setup.remove_referral_tag(&user_1, None);
-    setup.b_wrapper.check_esdt_balance(&user_1, WRAPPED_TOKEN, &rust_biguint!(referral_fee));
+    // the price dropped by more than the tolerance
+    setup.set_pair_price_multiplier(9_000u64);
+    let value = cost * 9_000u64 / MAX_PERCENTAGE;
+    setup.check_token_pnl(KNOWN_TOKEN_2, token_2_amount, cost, 0i64, value as i64 - cost as i64);
+    setup.sell_dust_token(vec![KNOWN_TOKEN_2]);
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+
+    setup.set_pair_price_multiplier(MAX_PERCENTAGE);
+    setup.sell_dust_token(vec![KNOWN_TOKEN_2]);
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_2, &rust_biguint!(0u64));
+
+    let proceeds = apply_slippage(cost);
+    setup.check_token_pnl(KNOWN_TOKEN_2, 0u64, 0u64, proceeds as i64 - cost as i64, 0i64);
 }
 
 #[test]
-fn test_accumulate_volume_and_update_tier() {
-    let known_token_amount_1 = 3_000_000u64;
-    let known_token_amount_2 = 2_500_000u64;
-    let tag = b"TEST5";
-
+fn test_sell_dust_tokens_batch() {
+    let token_2_amount = 4_000_000u64;
+    let token_3_amount = 2_000_000u64;
     let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
     setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.add_known_tokens(USDC_TOKEN, vec![KNOWN_TOKEN_3]);
     setup.resume();
-    let user_1 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
-    setup.register_referral_tag(&user_1, tag);
-
-    let user_2 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
-    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_1, &rust_biguint!(known_token_amount_1));
-    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_2, &rust_biguint!(known_token_amount_2));
-    let payments = [
-        TxTokenTransfer {
-            token_identifier: KNOWN_TOKEN_1.to_vec(),
-            nonce: 0,
-            value: rust_biguint!(known_token_amount_1 / 5)
-        },
-        TxTokenTransfer {
-            token_identifier: KNOWN_TOKEN_2.to_vec(),
-            nonce: 0,
-            value: rust_biguint!(known_token_amount_2 / 5)
-        }
-    ];
+    setup.b_wrapper.set_esdt_balance(setup.pair_wrapper.address_ref(), USDC_TOKEN, &rust_biguint!(INITIAL_SC_BALANCE));
 
-    let amount_out = compute_token_out_amount(known_token_amount_1 / 5, KNOWN_TOKEN_1) + compute_token_out_amount(known_token_amount_2 / 5, KNOWN_TOKEN_2);
-    let protocol_fee = amount_out * 500u64 / MAX_PERCENTAGE;
-    let referral_fee = protocol_fee * TIER_1_FEE_PERCENT / MAX_PERCENTAGE;
-    let total = amount_out - protocol_fee;
-    let length = payments.len();
-    setup.swap_dust_token(&payments, &user_2, length, total, None, Some(tag));
-    setup.swap_dust_token(&payments, &user_2, length, total, None, Some(tag));
-    setup.swap_dust_token(&payments, &user_2, length, total, None, Some(tag));
+    setup.b_wrapper.set_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+    setup.b_wrapper.set_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
 
-    setup.check_referral_fee_amount(tag, referral_fee * 3);
+    // not enough gas left for any token, nothing is sold and the cursor does not move
+    setup.set_sell_min_gas_left(u64::MAX);
+    setup.sell_dust_tokens_batch(vec![], false);
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
 
-    setup.update_tier(&user_1, Some("No tier upgrade found"));
+    // the whole registry is walked, tokens without balance are skipped
+    setup.set_sell_min_gas_left(0u64);
+    setup.sell_dust_tokens_batch(vec![KNOWN_TOKEN_2, KNOWN_TOKEN_3], true);
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_2, &rust_biguint!(0u64));
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_3, &rust_biguint!(0u64));
 }
 
 #[test]
-fn test_tier_with_0_fee() {
+fn test_sell_dust_tokens_batch_after_removal() {
+    let token_amount = 2_000_000u64;
     let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
-    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2, KNOWN_TOKEN_3, KNOWN_TOKEN_4]);
     setup.resume();
+    setup.set_sell_min_gas_left(0u64);
 
-    setup.add_tier_details(b"Bronze", 0u64, 500u64, Some("Tier already exists"));
-    setup.remove_tier_details(b"Bronze", None);
+    setup.b_wrapper.set_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_3, &rust_biguint!(token_amount));
+    setup.b_wrapper.set_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_4, &rust_biguint!(token_amount));
 
-    setup.add_tier_details(b"Bronze", 500u64, TIER_1_FEE_PERCENT, None);
-    setup.add_tier_details(b"Wood", 0u64, 0u64, None);
+    // a previous batch stopped before the second token, then the first one is removed and the last takes its place
+    setup.set_sell_cursor(0, 1);
+    setup.remove_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2]);
 
-    let user_1 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
-    let tag = "TEST5".to_string();
-    setup.register_referral_tag(&user_1, tag.as_bytes());
+    setup.sell_dust_tokens_batch(vec![KNOWN_TOKEN_4, KNOWN_TOKEN_3], true);
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_3, &rust_biguint!(0u64));
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_4, &rust_biguint!(0u64));
+}
 
-    setup.check_referral_fee_percentage(0u64, tag.as_bytes());
+#[test]
+fn test_sell_dust_tokens_batch_inactive_pair() {
+    let token_2_amount = 4_000_000u64;
+    let token_3_amount = 2_000_000u64;
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2, KNOWN_TOKEN_3]);
+    setup.resume();
+    setup.set_sell_min_gas_left(0u64);
+
+    let other_pair = setup.b_wrapper.create_sc_account(&rust_biguint!(0u64), Some(&setup.owner), pair_mock::contract_obj, "mocked wasm");
+    set_mock_pair_tokens(&mut setup.b_wrapper, &setup.owner, &other_pair, KNOWN_TOKEN_2, WRAPPED_TOKEN);
+    setup.b_wrapper.set_esdt_balance(other_pair.address_ref(), WRAPPED_TOKEN, &rust_biguint!(INITIAL_SC_BALANCE));
+    setup.add_pair_candidates(KNOWN_TOKEN_2, vec![other_pair.address_ref()]);
+    setup.set_execution_policy(KNOWN_TOKEN_2, token_2_amount / 2, 0u64);
+
+    setup.b_wrapper.set_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+    setup.b_wrapper.set_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
+
+    // the primary pair is inactive: the chunk is sold through the candidate and the token without one is skipped
+    setup.set_pair_state(State::Inactive);
+    setup.sell_dust_tokens_batch(vec![KNOWN_TOKEN_2], true);
+    setup.b_wrapper.check_esdt_balance(other_pair.address_ref(), KNOWN_TOKEN_2, &rust_biguint!(token_2_amount / 2));
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
+}
 
-    let token_amount = 3_000_000u64;
-    let user_2 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
-    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_1, &rust_biguint!(token_amount));
-    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_2, &rust_biguint!(token_amount));
+#[test]
+fn test_sell_dust_tokens_keeper_bounty() {
+    let token_1_amount = 3_000_000u64;
+    let max_bounty = 1_000u64;
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1]);
+    setup.resume();
+
+    let keeper = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.setup_keeper_selling(&keeper, 1_000u64, WRAPPED_TOKEN, max_bounty);
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_1, &rust_biguint!(token_1_amount));
     let payments = [
         TxTokenTransfer {
             token_identifier: KNOWN_TOKEN_1.to_vec(),
             nonce: 0,
-            value: rust_biguint!(token_amount)
-        },
-        TxTokenTransfer {
-            token_identifier: KNOWN_TOKEN_2.to_vec(),
-            nonce: 0,
-            value: rust_biguint!(token_amount)
+            value: rust_biguint!(token_1_amount)
         }
     ];
+    setup.swap_dust_token(&payments, &user, payments.len(), 0u64, None, None);
 
-    let amount_out = compute_token_out_amount(token_amount, KNOWN_TOKEN_1) + compute_token_out_amount(token_amount, KNOWN_TOKEN_2);
-    let protocol_fee = amount_out * 500u64 / MAX_PERCENTAGE;
-    let total = amount_out - protocol_fee;
-    setup.swap_dust_token(&payments, &user_2, payments.len(), total, None, Some(tag.as_bytes()));
-
-    setup.check_referral_fee_amount(tag.as_bytes(), 0u64);
+    setup.sell_dust_token_from(&user, vec![KNOWN_TOKEN_1], Some("Permission denied"));
 
-    setup.update_tier(&user_1, None);
-    setup.check_referral_fee_percentage(TIER_1_FEE_PERCENT, tag.as_bytes());
+    // 10% of the proceeds is over the maximum bounty
+    setup.sell_dust_token_from(&keeper, vec![KNOWN_TOKEN_1], None);
+    setup.b_wrapper.check_esdt_balance(&keeper, WRAPPED_TOKEN, &rust_biguint!(max_bounty));
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_1, &rust_biguint!(0u64));
 }
 
 #[test]
-fn test_remove_known_tokens() {
+fn test_sell_dust_tokens_usdc_proceeds() {
+    let token_3_amount = 2_000_000u64;
     let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
-    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2, KNOWN_TOKEN_3]);
-    setup.add_known_tokens(USDC_TOKEN, vec![KNOWN_TOKEN_4, KNOWN_TOKEN_5]);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1]);
+    setup.add_known_tokens(USDC_TOKEN, vec![KNOWN_TOKEN_3]);
     setup.resume();
+    setup.b_wrapper.set_esdt_balance(setup.pair_wrapper.address_ref(), USDC_TOKEN, &rust_biguint!(INITIAL_SC_BALANCE));
 
-    setup.remove_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
-    setup.check_all_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_3]);
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_3_amount)
+        }
+    ];
+    let usdc_proceeds = apply_slippage(compute_token_out_amount(token_3_amount, KNOWN_TOKEN_3));
+
+    // USDC proceeds are kept by default
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
+    setup.swap_dust_token(&payments, &user, 0, 0u64, None, None);
+    setup.sell_dust_token(vec![KNOWN_TOKEN_3]);
+    setup.check_reserve_status(USDC_TOKEN, usdc_proceeds, 0u64, 0u64);
+
+    // the kept USDC is part of the reserve and cannot be sold
+    setup.sell_dust_token(vec![USDC_TOKEN]);
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), USDC_TOKEN, &rust_biguint!(usdc_proceeds));
+
+    // forwarded USDC proceeds are sold for WEGLD right away
+    setup.set_forward_base_proceeds(true);
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
+    setup.swap_dust_token(&payments, &user, 0, 0u64, None, None);
+    setup.sell_dust_token(vec![KNOWN_TOKEN_3]);
+    setup.check_reserve_status(USDC_TOKEN, usdc_proceeds, 0u64, 0u64);
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), USDC_TOKEN, &rust_biguint!(usdc_proceeds));
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_3, &rust_biguint!(0u64));
+}
 
-    setup.remove_known_tokens(USDC_TOKEN, vec![KNOWN_TOKEN_4, KNOWN_TOKEN_5]);
-    setup.check_all_tokens(USDC_TOKEN, vec![]);
+#[test]
+fn test_swap_and_sell_dust_tokens_best_pair() {
+    let token_1_amount = 3_000_000u64;
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1]);
+    setup.resume();
+
+    // a second pool of the same tokens paying 20% more
+    let better_pair = setup.b_wrapper.create_sc_account(&rust_biguint!(0u64), Some(&setup.owner), pair_mock::contract_obj, "mocked wasm");
+    setup.b_wrapper
+        .execute_tx(&setup.owner, &better_pair, &rust_biguint!(0u64), |sc| {
+            sc.set_price_multiplier(12_000u64);
+        })
+        .assert_ok();
+    set_mock_pair_tokens(&mut setup.b_wrapper, &setup.owner, &better_pair, KNOWN_TOKEN_1, WRAPPED_TOKEN);
+    setup.b_wrapper.set_esdt_balance(better_pair.address_ref(), WRAPPED_TOKEN, &rust_biguint!(INITIAL_SC_BALANCE));
+    setup.add_pair_candidates(KNOWN_TOKEN_1, vec![better_pair.address_ref()]);
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_1, &rust_biguint!(token_1_amount));
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_1.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_1_amount)
+        }
+    ];
+
+    let amount_out = compute_token_out_amount(token_1_amount, KNOWN_TOKEN_1) * 12_000u64 / MAX_PERCENTAGE;
+    let fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    let total = amount_out - fee;
+
+    setup.swap_dust_token(&payments, &user, payments.len(), total, None, None);
+    setup.b_wrapper.check_esdt_balance(&user, WRAPPED_TOKEN, &rust_biguint!(total));
+
+    setup.sell_dust_token(vec![KNOWN_TOKEN_1]);
+    setup.b_wrapper.check_esdt_balance(better_pair.address_ref(), KNOWN_TOKEN_1, &rust_biguint!(token_1_amount));
+    setup.b_wrapper.check_esdt_balance(setup.pair_wrapper.address_ref(), KNOWN_TOKEN_1, &rust_biguint!(0u64));
+}
+
+#[test]
+fn test_swap_dust_tokens_through_base_token() {
+    let token_3_amount = 2_000_000u64;
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_base_tokens(vec![KNOWN_TOKEN_2], Some("Base token has no route to wrapped token"));
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2]);
+    setup.add_base_tokens(vec![KNOWN_TOKEN_2], None);
+    setup.set_token_enabled_with_err(KNOWN_TOKEN_2, false, Some("Cannot change the state of a base token"));
+    setup.add_known_tokens(KNOWN_TOKEN_2, vec![KNOWN_TOKEN_3]);
+    setup.resume();
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_3_amount)
+        }
+    ];
+
+    let base_amount = compute_token_out_amount(token_3_amount, KNOWN_TOKEN_3);
+    let amount_out = compute_token_out_amount(base_amount, KNOWN_TOKEN_2);
+    let fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    let total = amount_out - fee;
+
+    setup.swap_dust_token(&payments, &user, 0, total, None, None);
+    setup.b_wrapper.check_esdt_balance(&user, WRAPPED_TOKEN, &rust_biguint!(total));
+
+    // removing the route of a base token also drops it from the base tokens
+    setup.remove_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2]);
+    setup.add_base_tokens(vec![KNOWN_TOKEN_2], Some("Base token has no route to wrapped token"));
+
+    // the tokens registered under it are refunded
+    setup.set_refund_only_allowed(true);
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
+    setup.swap_dust_token_with_refunds(&payments, &user, 0, vec![(KNOWN_TOKEN_3, token_3_amount, RefundReason::NotBaseToken)]);
+    setup.b_wrapper.check_esdt_balance(&user, KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
+}
+
+#[test]
+fn test_swap_dust_tokens_any_order() {
+    let token_2_amount = 3_000_000u64;
+    let token_3_amount = 2_000_000u64;
+    let unkown_token_amount = 2_500_000u64;
+
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.add_known_tokens(USDC_TOKEN, vec![KNOWN_TOKEN_3]);
+    setup.resume();
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
+    setup.b_wrapper.set_esdt_balance(&user, UNKOWN_TOKEN_3, &rust_biguint!(unkown_token_amount));
+
+    // the USDC paired token comes first, which swapDustTokens would need num_wegld to tell apart
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_3_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: UNKOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(unkown_token_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_2_amount)
+        }
+    ];
+
+    let usdc_amount = compute_token_out_amount(token_3_amount, KNOWN_TOKEN_3);
+    let amount_out = compute_token_out_amount(token_2_amount, KNOWN_TOKEN_2) + compute_token_out_amount(usdc_amount, USDC_TOKEN);
+    let fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    let total = amount_out - fee;
+
+    setup.swap_dust_token(&payments, &user, 0, total, Some("Invalid payments"), None);
+    setup.swap_dust_tokens_any_order(&payments, &user, total, None);
+    setup.b_wrapper.check_esdt_balance(&user, WRAPPED_TOKEN, &rust_biguint!(total));
+    setup.b_wrapper.check_esdt_balance(&user, UNKOWN_TOKEN_3, &rust_biguint!(unkown_token_amount));
+}
+
+#[test]
+fn test_swap_dust_tokens_to_usdc_output() {
+    let token_2_amount = 4_000_000u64;
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2]);
+    setup.add_output_token(USDC_TOKEN, vec![USDC_TOKEN]);
+    setup.top_up(USDC_TOKEN, AMOUNT_OUT);
+    setup.resume();
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_2_amount)
+        }
+    ];
+
+    let amount_out = compute_token_out_amount(compute_token_out_amount(token_2_amount, KNOWN_TOKEN_2), WRAPPED_TOKEN);
+    let fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    let total = amount_out - fee;
+
+    setup.swap_dust_token_for_output(&payments, &user, payments.len(), Some(UNKOWN_TOKEN_3), total, Some("Output token not allowed"), None);
+    setup.swap_dust_token_for_output(&payments, &user, payments.len(), Some(USDC_TOKEN), total, None, None);
+
+    setup.b_wrapper.check_esdt_balance(&user, USDC_TOKEN, &rust_biguint!(total));
+    setup.b_wrapper.check_esdt_balance(&user, WRAPPED_TOKEN, &rust_biguint!(0u64));
+    setup.check_protocol_fee_amount(USDC_TOKEN, fee);
+    setup.check_protocol_fee_amount(WRAPPED_TOKEN, 0u64);
+}
+
+#[test]
+fn test_swap_dust_tokens_to_native_egld() {
+    let token_1_amount = 3_000_000u64;
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1]);
+    setup.resume();
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_1, &rust_biguint!(token_1_amount));
+
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_1.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_1_amount)
+        }
+    ];
+
+    let amount_out = compute_token_out_amount(token_1_amount, KNOWN_TOKEN_1);
+    let fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    let total = amount_out - fee;
+
+    setup.swap_dust_token_for_output(&payments, &user, payments.len(), Some(b"EGLD"), total, Some("Wrapping contract not set"), None);
+
+    // the pair mock also acts as the wrapping contract
+    let wrapping_address = setup.pair_wrapper.address_ref().clone();
+    setup.b_wrapper.set_egld_balance(&wrapping_address, &rust_biguint!(amount_out));
+    setup.set_wrapping_contract(&wrapping_address);
+    setup.swap_dust_token_for_output(&payments, &user, payments.len(), Some(b"EGLD"), total, None, None);
+
+    setup.b_wrapper.check_egld_balance(&user, &rust_biguint!(total));
+    setup.b_wrapper.check_esdt_balance(&user, WRAPPED_TOKEN, &rust_biguint!(0u64));
+    setup.check_protocol_fee_amount(WRAPPED_TOKEN, fee);
+}
+
+#[test]
+fn test_swap_dust_tokens_atomic_mode() {
+    let token_1_amount = 3_000_000u64;
+    let token_2_amount = 4_000_000u64;
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.set_atomic_swap_mode(true);
+    setup.resume();
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_1, &rust_biguint!(token_1_amount));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_1.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_1_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_2_amount)
+        }
+    ];
+
+    let amount_out = apply_slippage(compute_token_out_amount(token_1_amount, KNOWN_TOKEN_1))
+        + apply_slippage(compute_token_out_amount(token_2_amount, KNOWN_TOKEN_2));
+    let fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    let total = amount_out - fee;
+
+    setup.swap_dust_token(&payments, &user, payments.len(), total, None, None);
+
+    setup.b_wrapper.check_esdt_balance(&user, WRAPPED_TOKEN, &rust_biguint!(total));
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_1, &rust_biguint!(0u64));
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_2, &rust_biguint!(0u64));
+    setup.check_protocol_fee_amount(WRAPPED_TOKEN, fee);
+}
+
+#[test]
+fn test_reserve_accounting() {
+    let token_1_amount = 3_000_000u64;
+    let tag = b"TEST5";
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1]);
+    setup.add_output_token(USDC_TOKEN, vec![USDC_TOKEN]);
+    setup.resume();
+
+    let user_1 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.register_referral_tag(&user_1, tag);
+
+    let user_2 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_1, &rust_biguint!(token_1_amount));
+
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_1.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_1_amount)
+        }
+    ];
+
+    // no USDC was topped up
+    setup.swap_dust_token_for_output(&payments, &user_2, payments.len(), Some(USDC_TOKEN), 0, Some("Insufficient reserve"), Some(tag));
+
+    let amount_out = compute_token_out_amount(token_1_amount, KNOWN_TOKEN_1);
+    let fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    let referral_fee = fee * TIER_1_FEE_PERCENT / MAX_PERCENTAGE;
+    let total = amount_out - fee;
+    setup.swap_dust_token(&payments, &user_2, payments.len(), total, None, Some(tag));
+
+    setup.check_reserve_status(WRAPPED_TOKEN, INITIAL_SC_BALANCE - amount_out, fee - referral_fee, referral_fee);
+    setup.check_reserve_status(USDC_TOKEN, 0u64, 0u64, 0u64);
+}
+
+#[test]
+fn test_swap_dust_tokens_at_safe_price() {
+    let known_token_1_amount = 3_000_000u64;
+    let known_token_2_amount = 4_000_000u64;
+
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.resume();
+
+    // token 1 trades 5% above its safe price, token 2 trades at twice its safe price
+    setup.set_safe_price_rate(KNOWN_TOKEN_1, 9_500u64);
+    setup.set_safe_price_rate(KNOWN_TOKEN_2, 5_000u64);
+    setup.set_max_price_deviation(KNOWN_TOKEN_1, 1_000u64);
+    setup.set_max_price_deviation(KNOWN_TOKEN_2, 1_000u64);
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_1, &rust_biguint!(known_token_1_amount));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(known_token_2_amount));
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_1.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(known_token_1_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(known_token_2_amount)
+        }
+    ];
+
+    let amount_out = compute_token_out_amount(known_token_1_amount, KNOWN_TOKEN_1) * 9_500u64 / MAX_PERCENTAGE;
+    let fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    let total = amount_out - fee;
+
+    setup.check_swap_quote(&payments, payments.len(), None, total, fee, 0u64, vec![KNOWN_TOKEN_2]);
+    setup.swap_dust_token(&payments, &user, payments.len(), total, None, None);
+
+    setup.b_wrapper.check_esdt_balance(&user, WRAPPED_TOKEN, &rust_biguint!(total));
+    setup.b_wrapper.check_esdt_balance(&user, KNOWN_TOKEN_1, &rust_biguint!(0u64));
+    setup.b_wrapper.check_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(known_token_2_amount));
+}
+
+#[test]
+fn test_swap_dust_tokens_over_caps() {
+    let known_token_amount = 3_000_000u64;
+
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.resume();
+
+    let value = compute_token_out_amount(known_token_amount, KNOWN_TOKEN_2);
+    let max_swap_value = value / 2;
+    let epoch_inflow_cap = value * 5 / 6;
+    // USDC is a base token, its payments cannot be refunded
+    setup.set_token_caps_with_err(KNOWN_TOKEN_1, max_swap_value, epoch_inflow_cap, Some("Base tokens cannot be capped"));
+    setup.set_token_caps(KNOWN_TOKEN_2, max_swap_value, epoch_inflow_cap);
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(known_token_amount)
+        }
+    ];
+
+    // only half of the payment fits in the per swap limit
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(known_token_amount));
+    let total = max_swap_value - max_swap_value * 500u64 / MAX_PERCENTAGE;
+    setup.swap_dust_token(&payments, &user, payments.len(), total, None, None);
+    setup.b_wrapper.check_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(known_token_amount / 2));
+    setup.check_remaining_epoch_inflow(KNOWN_TOKEN_2, epoch_inflow_cap - max_swap_value);
+
+    // the rest of the epoch cap is lower than the per swap limit
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(known_token_amount));
+    let remaining_value = epoch_inflow_cap - max_swap_value;
+    let second_total = remaining_value - remaining_value * 500u64 / MAX_PERCENTAGE;
+    setup.swap_dust_token(&payments, &user, payments.len(), second_total, None, None);
+    setup.b_wrapper.check_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(known_token_amount * 2 / 3));
+    setup.b_wrapper.check_esdt_balance(&user, WRAPPED_TOKEN, &rust_biguint!(total + second_total));
+    setup.check_remaining_epoch_inflow(KNOWN_TOKEN_2, 0u64);
+
+    // the cap is used up, everything would be refunded
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(known_token_amount));
+    setup.swap_dust_token(&payments, &user, payments.len(), 0u64, Some("Zero amount cannot be claimed"), None);
+
+    // the cap resets in the next epoch
+    setup.b_wrapper.set_block_epoch(1u64);
+    setup.check_remaining_epoch_inflow(KNOWN_TOKEN_2, epoch_inflow_cap);
+}
+
+#[test]
+fn test_swap_dust_tokens_capped_to_zero_quote() {
+    let token_2_amount = 3_000_000u64;
+    let token_3_amount = 3_000_000u64;
+
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2, KNOWN_TOKEN_3]);
+    setup.resume();
+
+    // the accepted part of the payment is too small to quote to anything
+    let max_swap_value = 1u64;
+    let accepted_amount = token_2_amount * max_swap_value / compute_token_out_amount(token_2_amount, KNOWN_TOKEN_2);
+    assert_eq!(compute_token_out_amount(accepted_amount, KNOWN_TOKEN_2), 0u64);
+    setup.set_token_caps(KNOWN_TOKEN_2, max_swap_value, 0u64);
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_3, &rust_biguint!(token_3_amount));
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_2_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_3_amount)
+        }
+    ];
+
+    setup.swap_dust_token_with_refunds(&payments, &user, payments.len(), vec![
+        (KNOWN_TOKEN_2, token_2_amount - accepted_amount, RefundReason::CapExceeded),
+        (KNOWN_TOKEN_2, accepted_amount, RefundReason::ZeroQuote),
+    ]);
+    setup.b_wrapper.check_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+    setup.b_wrapper.check_esdt_balance(setup.c_wrapper.address_ref(), KNOWN_TOKEN_2, &rust_biguint!(0u64));
+}
+
+#[test]
+fn test_refund_unknown_tokens() {
+    let known_token_amount = 3_000_000u64;
+    let unkown_token_amount = 2_500_000u64;
+
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.resume();
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_1, &rust_biguint!(known_token_amount));
+    setup.b_wrapper.set_esdt_balance(&user, UNKOWN_TOKEN_3, &rust_biguint!(unkown_token_amount));
+
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_1.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(known_token_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: UNKOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(unkown_token_amount)
+        }
+    ];
+
+    let amount_out = compute_token_out_amount(known_token_amount, KNOWN_TOKEN_1);
+    let fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    let total = amount_out - fee;
+
+    setup.swap_dust_token(&payments, &user, payments.len(), total, None, None);
+
+    setup.b_wrapper.check_esdt_balance(&user, KNOWN_TOKEN_1, &rust_biguint!(0u64));
+    setup.b_wrapper.check_esdt_balance(&user, UNKOWN_TOKEN_3, &rust_biguint!(unkown_token_amount));
+}
+
+#[test]
+fn test_swap_sell_refund_and_fee_claim_events() {
+    let token_2_amount = 3_000_000u64;
+    let unknown_token_amount = 2_500_000u64;
+    let tag = b"TEST9";
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2]);
+    setup.resume();
+
+    let user_1 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.register_referral_tag(&user_1, tag);
+
+    let user_2 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+    setup.b_wrapper.set_esdt_balance(&user_2, UNKOWN_TOKEN_3, &rust_biguint!(unknown_token_amount));
+
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_2_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: UNKOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(unknown_token_amount)
+        }
+    ];
+
+    let amount_out = compute_token_out_amount(token_2_amount, KNOWN_TOKEN_2);
+    let fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    let total = amount_out - fee;
+    setup.check_swap_event(&payments, &user_2, payments.len(), total, fee, vec![(UNKOWN_TOKEN_3, unknown_token_amount)]);
+
+    // a refund-only swap reports every payment as refunded and nothing paid out
+    setup.set_refund_only_allowed(true);
+    setup.check_swap_event(&payments[1..], &user_2, 1, 0, 0, vec![(UNKOWN_TOKEN_3, unknown_token_amount)]);
+
+    setup.check_sell_event(KNOWN_TOKEN_2, token_2_amount, apply_slippage(amount_out), 0);
+
+    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_2, &rust_biguint!(token_2_amount));
+    setup.swap_dust_token(&payments[..1], &user_2, 1, total, None, Some(tag));
+    let referral_fee = fee * TIER_1_FEE_PERCENT / MAX_PERCENTAGE;
+
+    setup.check_claim_referral_fees_event(&user_1, tag, referral_fee);
+    setup.check_extract_fees_event(fee + fee - referral_fee);
+}
+
+#[test]
+fn test_register_referral_tag() {
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.resume();
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    let tag = "TEST5".to_string();
+    setup.register_referral_tag(&user, tag.as_bytes());
+    setup.check_registered_tags(tag.as_bytes(), &user);
+    setup.check_referral_fee_percentage(TIER_1_FEE_PERCENT, tag.as_bytes());
+}
+
+#[test]
+fn test_update_referral_tag_percent() {
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.resume();
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    let tag = "TEST5".to_string();
+    setup.register_referral_tag(&user, tag.as_bytes());
+
+    setup.check_referral_fee_percentage(500u64, tag.as_bytes());
+    setup.set_referral_fee_percentage(1000u64, tag.as_bytes());
+    setup.check_referral_fee_percentage(1000u64, tag.as_bytes());
+}
+
+#[test]
+fn test_swap_token_with_referral_tag() {
+    let known_token_amount_1 = 3_000_000u64;
+    let known_token_amount_2 = 2_500_000u64;
+    let tag = b"TEST5";
+
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.resume();
+    let user_1 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.register_referral_tag(&user_1, tag);
+
+    let user_2 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_1, &rust_biguint!(known_token_amount_1));
+    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_2, &rust_biguint!(known_token_amount_2));
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_1.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(known_token_amount_1)
+        },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(known_token_amount_2)
+        }
+    ];
+
+    let amount_out = compute_token_out_amount(known_token_amount_1, KNOWN_TOKEN_1) + compute_token_out_amount(known_token_amount_2, KNOWN_TOKEN_2);
+    let fee = amount_out * TIER_1_FEE_PERCENT / MAX_PERCENTAGE;
+    let referral_fee = fee * TIER_1_FEE_PERCENT / MAX_PERCENTAGE;
+    let total = amount_out - fee;
+    setup.swap_dust_token(&payments, &user_2, payments.len(), total, None, Some(tag));
+    setup.check_referral_fee_amount(tag, referral_fee);
+
+    setup.remove_referral_tag(&user_1, None);
+    setup.b_wrapper.check_esdt_balance(&user_1, WRAPPED_TOKEN, &rust_biguint!(referral_fee));
+}
+
+#[test]
+fn test_swap_dust_tokens_to() {
+    let known_token_amount_1 = 3_000_000u64;
+    let unkown_token_amount = 2_500_000u64;
+    let tag = b"TEST5";
+
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1]);
+    setup.resume();
+    let user_1 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.register_referral_tag(&user_1, tag);
+
+    let user_2 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    let destination = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    let refund_address = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_1, &rust_biguint!(known_token_amount_1));
+    setup.b_wrapper.set_esdt_balance(&user_2, UNKOWN_TOKEN_3, &rust_biguint!(unkown_token_amount));
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_1.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(known_token_amount_1)
+        },
+        TxTokenTransfer {
+            token_identifier: UNKOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(unkown_token_amount)
+        }
+    ];
+
+    let amount_out = compute_token_out_amount(known_token_amount_1, KNOWN_TOKEN_1);
+    let fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    let referral_fee = fee * TIER_1_FEE_PERCENT / MAX_PERCENTAGE;
+    let total = amount_out - fee;
+    setup.swap_dust_tokens_to(&payments, &user_2, payments.len(), &destination, &refund_address, Some(tag));
+
+    setup.b_wrapper.check_esdt_balance(&destination, WRAPPED_TOKEN, &rust_biguint!(total));
+    setup.b_wrapper.check_esdt_balance(&refund_address, UNKOWN_TOKEN_3, &rust_biguint!(unkown_token_amount));
+    setup.b_wrapper.check_esdt_balance(&user_2, WRAPPED_TOKEN, &rust_biguint!(0u64));
+    setup.b_wrapper.check_esdt_balance(&user_2, UNKOWN_TOKEN_3, &rust_biguint!(0u64));
+    setup.check_referral_fee_amount(tag, referral_fee);
+    setup.check_protocol_fee_amount(WRAPPED_TOKEN, fee - referral_fee);
+}
+
+#[test]
+fn test_swap_dust_tokens_and_call() {
+    let known_token_amount_1 = 3_000_000u64;
+
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1]);
+    setup.resume();
+    let target = setup.pair_wrapper.address_ref().clone();
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_1, &rust_biguint!(known_token_amount_1));
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_1.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(known_token_amount_1)
+        }
+    ];
+
+    let amount_out = compute_token_out_amount(known_token_amount_1, KNOWN_TOKEN_1);
+    let total = amount_out - amount_out * 500u64 / MAX_PERCENTAGE;
+    setup.swap_dust_tokens_and_call(&payments, &user, &target, b"depositFor", vec![rust_biguint!(total).to_bytes_be()], Some("Call target not allowed"));
+
+    // a failing call reverts the swap
+    setup.add_call_target(&target, b"depositFor");
+    setup.swap_dust_tokens_and_call(&payments, &user, &target, b"depositFor", vec![rust_biguint!(total + 1).to_bytes_be()], Some("Deposit too low"));
+    setup.b_wrapper.check_esdt_balance(&user, KNOWN_TOKEN_1, &rust_biguint!(known_token_amount_1));
+
+    setup.swap_dust_tokens_and_call(&payments, &user, &target, b"depositFor", vec![rust_biguint!(total).to_bytes_be()], None);
+    setup.b_wrapper.check_esdt_balance(&user, KNOWN_TOKEN_1, &rust_biguint!(0u64));
+    setup.b_wrapper.check_esdt_balance(&user, WRAPPED_TOKEN, &rust_biguint!(0u64));
+    setup.check_pair_deposit(&user, total);
+}
+
+#[test]
+fn test_swap_quote_matches_swap() {
+    let known_token_1_amount = 3_000_000u64;
+    let known_token_3_amount = 2_000_000u64;
+    let unkown_token_amount = 2_500_000u64;
+    let tag = b"TEST5";
+
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.add_known_tokens(USDC_TOKEN, vec![KNOWN_TOKEN_3]);
+    setup.resume();
+    let user_1 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.register_referral_tag(&user_1, tag);
+
+    let user_2 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_1, &rust_biguint!(known_token_1_amount));
+    setup.b_wrapper.set_esdt_balance(&user_2, UNKOWN_TOKEN_3, &rust_biguint!(unkown_token_amount));
+    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_3, &rust_biguint!(known_token_3_amount));
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_1.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(known_token_1_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: UNKOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(unkown_token_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_3.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(known_token_3_amount)
+        }
+    ];
+
+    // the USDC leg is added to the first payment, which is USDC as well
+    let usdc_amount_out = compute_token_out_amount(known_token_3_amount, KNOWN_TOKEN_3);
+    let amount_out = compute_token_out_amount(known_token_1_amount + usdc_amount_out, KNOWN_TOKEN_1);
+    let fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    let referral_fee = fee * TIER_1_FEE_PERCENT / MAX_PERCENTAGE;
+    let total = amount_out - fee;
+
+    setup.check_swap_quote(&payments, 2, Some(tag), total, fee - referral_fee, referral_fee, vec![UNKOWN_TOKEN_3]);
+    setup.check_referral_fee_amount(tag, 0u64);
+
+    setup.check_swap_result(&payments, &user_2, 2, Some(tag), total, fee - referral_fee, referral_fee, vec![UNKOWN_TOKEN_3]);
+    setup.b_wrapper.check_esdt_balance(&user_2, WRAPPED_TOKEN, &rust_biguint!(total));
+    setup.b_wrapper.check_esdt_balance(&user_2, UNKOWN_TOKEN_3, &rust_biguint!(unkown_token_amount));
+    setup.check_referral_fee_amount(tag, referral_fee);
+    setup.check_protocol_fee_amount(WRAPPED_TOKEN, fee - referral_fee);
+}
+
+#[test]
+fn test_accumulate_volume_and_update_tier() {
+    let known_token_amount_1 = 3_000_000u64;
+    let known_token_amount_2 = 2_500_000u64;
+    let tag = b"TEST5";
+
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.resume();
+    let user_1 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.register_referral_tag(&user_1, tag);
+
+    let user_2 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_1, &rust_biguint!(known_token_amount_1));
+    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_2, &rust_biguint!(known_token_amount_2));
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_1.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(known_token_amount_1 / 5)
+        },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(known_token_amount_2 / 5)
+        }
+    ];
+
+    let amount_out = compute_token_out_amount(known_token_amount_1 / 5, KNOWN_TOKEN_1) + compute_token_out_amount(known_token_amount_2 / 5, KNOWN_TOKEN_2);
+    let protocol_fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    let referral_fee = protocol_fee * TIER_1_FEE_PERCENT / MAX_PERCENTAGE;
+    let total = amount_out - protocol_fee;
+    let length = payments.len();
+    setup.swap_dust_token(&payments, &user_2, length, total, None, Some(tag));
+    setup.swap_dust_token(&payments, &user_2, length, total, None, Some(tag));
+    setup.swap_dust_token(&payments, &user_2, length, total, None, Some(tag));
+
+    setup.check_referral_fee_amount(tag, referral_fee * 3);
+
+    setup.update_tier(&user_1, Some("No tier upgrade found"));
+}
+
+#[test]
+fn test_tier_with_0_fee() {
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.resume();
+
+    setup.add_tier_details(b"Bronze", 0u64, 500u64, Some("Tier already exists"));
+    setup.remove_tier_details(b"Bronze", None);
+
+    setup.add_tier_details(b"Bronze", 500u64, TIER_1_FEE_PERCENT, None);
+    setup.add_tier_details(b"Wood", 0u64, 0u64, None);
+
+    let user_1 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    let tag = "TEST5".to_string();
+    setup.register_referral_tag(&user_1, tag.as_bytes());
+
+    setup.check_referral_fee_percentage(0u64, tag.as_bytes());
+
+    let token_amount = 3_000_000u64;
+    let user_2 = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_1, &rust_biguint!(token_amount));
+    setup.b_wrapper.set_esdt_balance(&user_2, KNOWN_TOKEN_2, &rust_biguint!(token_amount));
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_1.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(token_amount)
+        }
+    ];
+
+    let amount_out = compute_token_out_amount(token_amount, KNOWN_TOKEN_1) + compute_token_out_amount(token_amount, KNOWN_TOKEN_2);
+    let protocol_fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    let total = amount_out - protocol_fee;
+    setup.swap_dust_token(&payments, &user_2, payments.len(), total, None, Some(tag.as_bytes()));
+
+    setup.check_referral_fee_amount(tag.as_bytes(), 0u64);
+
+    setup.update_tier(&user_1, None);
+    setup.check_referral_fee_percentage(TIER_1_FEE_PERCENT, tag.as_bytes());
+}
+
+#[test]
+fn test_remove_known_tokens() {
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2, KNOWN_TOKEN_3]);
+    setup.add_known_tokens(USDC_TOKEN, vec![KNOWN_TOKEN_4, KNOWN_TOKEN_5]);
+    setup.resume();
+
+    setup.remove_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.check_all_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_3]);
+
+    setup.remove_known_tokens(USDC_TOKEN, vec![KNOWN_TOKEN_4, KNOWN_TOKEN_5]);
+    setup.check_all_tokens(USDC_TOKEN, vec![]);
+}
+
+#[test]
+fn test_update_token_registry() {
+    let known_token_amount = 3_000_000u64;
+    let disabled_token_amount = 2_500_000u64;
+
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2, KNOWN_TOKEN_3]);
+    setup.resume();
+
+    setup.update_token_threshold(KNOWN_TOKEN_3, 7u64);
+    setup.set_token_enabled(KNOWN_TOKEN_2, false);
+    setup.check_token_configs(WRAPPED_TOKEN, 1, 5, vec![(KNOWN_TOKEN_2, false, 2u64), (KNOWN_TOKEN_3, true, 7u64)]);
+    setup.check_token_configs(WRAPPED_TOKEN, 0, 1, vec![(KNOWN_TOKEN_1, true, 2u64)]);
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_1, &rust_biguint!(known_token_amount));
+    setup.b_wrapper.set_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(disabled_token_amount));
+
+    let payments = [
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_1.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(known_token_amount)
+        },
+        TxTokenTransfer {
+            token_identifier: KNOWN_TOKEN_2.to_vec(),
+            nonce: 0,
+            value: rust_biguint!(disabled_token_amount)
+        }
+    ];
+
+    let amount_out = compute_token_out_amount(known_token_amount, KNOWN_TOKEN_1);
+    let fee = amount_out * 500u64 / MAX_PERCENTAGE;
+    setup.swap_dust_token(&payments, &user, payments.len(), amount_out - fee, None, None);
+    setup.b_wrapper.check_esdt_balance(&user, KNOWN_TOKEN_2, &rust_biguint!(disabled_token_amount));
+
+    setup.set_token_enabled(KNOWN_TOKEN_2, true);
+    setup.check_token_configs(WRAPPED_TOKEN, 1, 1, vec![(KNOWN_TOKEN_2, true, 2u64)]);
+}
+
+#[test]
+fn test_migrate_token_registry() {
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.migrate_legacy_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+
+    setup.check_all_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.check_token_configs(WRAPPED_TOKEN, 0, 10, vec![(KNOWN_TOKEN_1, true, 2u64), (KNOWN_TOKEN_2, true, 2u64)]);
+    // USDC is routed to WEGLD again, so it is a base token again
+    setup.check_base_tokens(vec![USDC_TOKEN]);
+
+    // tokens that were never registered have an empty route and threshold
+    setup.check_token_route(KNOWN_TOKEN_3, vec![]);
+    setup.check_token_threshold(KNOWN_TOKEN_3, 0u64);
+}
+
+#[test]
+fn test_migrate_fee_storage() {
+    let protocol_fees = 1_000u64;
+    let tag_fees = 200u64;
+    let tag = b"TEST6";
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.resume();
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.register_referral_tag(&user, tag);
+    setup.b_wrapper.set_esdt_balance(setup.c_wrapper.address_ref(), WRAPPED_TOKEN, &rust_biguint!(INITIAL_SC_BALANCE + protocol_fees + tag_fees));
+
+    setup.migrate_legacy_fees(protocol_fees, tag, tag_fees);
+    setup.check_protocol_fee_amount(WRAPPED_TOKEN, protocol_fees);
+    setup.check_referral_fee_amount(tag, tag_fees);
+    setup.check_reserve_status(WRAPPED_TOKEN, INITIAL_SC_BALANCE, protocol_fees, tag_fees);
+
+    // the migrated fees are paid out like fees collected after the upgrade
+    setup.remove_referral_tag(&user, None);
+    setup.b_wrapper.check_esdt_balance(&user, WRAPPED_TOKEN, &rust_biguint!(tag_fees));
+    setup.check_reserve_status(WRAPPED_TOKEN, INITIAL_SC_BALANCE, protocol_fees, 0u64);
+}
+
+#[test]
+fn test_migrate_reserve() {
+    let protocol_fees = 1_000u64;
+    let tag_fees = 200u64;
+    let other_tag_fees = 300u64;
+    let tag = b"TEST7";
+    let other_tag = b"TEST8";
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.resume();
+
+    let user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.register_referral_tag(&user, tag);
+    let other_user = setup.b_wrapper.create_user_account(&rust_biguint!(0u64));
+    setup.register_referral_tag(&other_user, other_tag);
+    let balance = INITIAL_SC_BALANCE + protocol_fees + tag_fees + other_tag_fees;
+    setup.b_wrapper.set_esdt_balance(setup.c_wrapper.address_ref(), WRAPPED_TOKEN, &rust_biguint!(balance));
+    setup.migrate_legacy_fees(protocol_fees, tag, tag_fees);
+    setup.set_legacy_tag_fees(other_tag, other_tag_fees);
+
+    // everything that is not migrated fees becomes reserve, once
+    setup.migrate_reserve(true, None);
+    setup.check_reserve_status(WRAPPED_TOKEN, INITIAL_SC_BALANCE + other_tag_fees, protocol_fees, tag_fees);
+    setup.migrate_reserve(false, Some("Reserve already set"));
+
+    // tag fees migrated afterwards leave the reserve instead of being counted twice
+    setup.migrate_legacy_fees(0u64, other_tag, other_tag_fees);
+    setup.check_reserve_status(WRAPPED_TOKEN, INITIAL_SC_BALANCE, protocol_fees, tag_fees + other_tag_fees);
+}
+
+#[test]
+fn test_repair_token_registry() {
+    let mut setup = DustConvertorSetup::new(dust_converter::contract_obj, WRAPPED_TOKEN, USDC_TOKEN, pair_mock::contract_obj);
+    setup.add_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.add_known_tokens(USDC_TOKEN, vec![KNOWN_TOKEN_3]);
+    setup.check_registry_issues(vec![], vec![]);
+
+    setup.set_registry_entry(WRAPPED_TOKEN, KNOWN_TOKEN_3, true);
+    setup.set_registry_entry(USDC_TOKEN, KNOWN_TOKEN_4, true);
+    setup.set_registry_entry(WRAPPED_TOKEN, KNOWN_TOKEN_2, false);
+    setup.check_registry_issues(vec![KNOWN_TOKEN_2], vec![
+        (KNOWN_TOKEN_3, WRAPPED_TOKEN, RegistryIssueKind::DuplicateEntry),
+        (KNOWN_TOKEN_4, USDC_TOKEN, RegistryIssueKind::MissingConfig),
+        (KNOWN_TOKEN_2, WRAPPED_TOKEN, RegistryIssueKind::NotInRegistry),
+    ]);
+
+    setup.remove_known_tokens_with_err(USDC_TOKEN, vec![KNOWN_TOKEN_1], Some("Token registered under another output token"));
+    setup.remove_known_tokens_with_err(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2], Some("Token not in registry"));
+    setup.remove_known_tokens_with_err(USDC_TOKEN, vec![KNOWN_TOKEN_4], Some("Token not registered"));
+
+    setup.repair_token_registry(vec![KNOWN_TOKEN_2]);
+    setup.check_registry_issues(vec![KNOWN_TOKEN_2], vec![]);
+    setup.check_all_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_1, KNOWN_TOKEN_2]);
+    setup.check_all_tokens(USDC_TOKEN, vec![KNOWN_TOKEN_3]);
+
+    setup.remove_known_tokens(WRAPPED_TOKEN, vec![KNOWN_TOKEN_2]);
 }
 
 #[test]
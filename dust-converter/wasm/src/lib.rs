@@ -5,9 +5,9 @@
 ////////////////////////////////////////////////////
 
 // Init:                                 1
-// Endpoints:                           35
+// Endpoints:                           108
 // Async Callback (empty):               1
-// Total number of exported functions:  37
+// Total number of exported functions:  110
 
 #![no_std]
 
@@ -15,19 +15,75 @@ elrond_wasm_node::wasm_endpoints! {
     dust_converter
     (
         swapDustTokens
+        swapDustTokensTo
+        swapDustTokensAndCall
+        swapDustTokensAnyOrder
+        getSwapQuote
+        getSwapQuoteAnyOrder
         sellDustTokens
+        sellDustTokensBatch
         topUp
         extractFees
         setFeePercentage
+        setWrappingContract
+        setRouterAddress
         setSlippagePercentage
+        setAtomicSwapMode
+        setTokenAtomicSwapMode
+        addOutputToken
+        addBaseTokens
+        removeBaseTokens
+        removeOutputToken
+        addKeepers
+        removeKeepers
+        setKeeperOnlySelling
+        setKeeperBountyPercentage
+        setMaxKeeperBounty
+        setSellMinGasLeft
+        setForwardBaseProceeds
+        setRefundOnlyAllowed
+        setMaxPriceDeviation
+        setMinQuote
+        setTokenCaps
         addKnownTokens
+        updateTokenRoute
+        updateTokenThreshold
+        setTokenEnabled
+        migrateTokenRegistry
+        addPairCandidates
+        removePairCandidates
+        addCallTarget
+        removeCallTarget
         removeKnownTokens
-        getAllTokens
+        getTokenCapStatus
+        getTokenRoute
         getTokenThreshold
+        getAllTokens
+        getTokenConfigs
+        getTokenCount
+        getTokenConfig
+        getCallTargetEndpoints
+        getPairCandidates
         getProtocolFeePercent
         getSlippagePercent
         getWrappedTokenId
         getUsdcTokenId
+        getBaseTokens
+        getAtomicSwapMode
+        getTokenAtomicSwapMode
+        getMaxPriceDeviation
+        getMinQuote
+        getKeeperOnlySelling
+        getKeeperBountyPercent
+        getMaxKeeperBounty
+        getSellMinGasLeft
+        getSellCursor
+        getForwardBaseProceeds
+        getRefundOnlyAllowed
+        getRouterAddress
+        getWrappingContract
+        getOutputTokens
+        getOutputTokenRoute
         registerReferralTag
         claimReferralFees
         updateTier
@@ -35,11 +91,28 @@ elrond_wasm_node::wasm_endpoints! {
         removeTierDetails
         setReferralFeePercentage
         removeReferralTag
+        migrateFeeStorage
+        migrateReserve
         getCollectedFeeAmount
         getUserTag
         getReferralFeePercentage
         getTierDetails
         getTagAccumulatedVolume
+        getReserveStatus
+        getReserveAmount
+        getCollectedProtocolFees
+        getReferralFeesOwed
+        getKeeperBountiesPaid
+        setSellLossTolerance
+        getTokenPnl
+        getRealizedPnl
+        getSellLossTolerance
+        setExecutionPolicy
+        removeExecutionPolicy
+        getSellProgress
+        getExecutionPolicy
+        checkTokenRegistry
+        repairTokenRegistry
         addAdmin
         removeAdmin
         updateOwnerOrAdmin
@@ -33,12 +33,14 @@ pub trait PausableModule: permissions_module::PermissionsModule {
     fn pause(&self) {
         self.require_caller_has_pause_permissions();
         self.state().set(State::Inactive);
+        self.pause_event(&self.blockchain().get_caller());
     }
 
     #[endpoint]
     fn resume(&self) {
         self.require_caller_has_pause_permissions();
         self.state().set(State::Active);
+        self.resume_event(&self.blockchain().get_caller());
     }
 
     #[inline]
@@ -46,6 +48,12 @@ pub trait PausableModule: permissions_module::PermissionsModule {
         require!(self.state().get() == State::Active, "State is not active");
     }
 
+    #[event("pause")]
+    fn pause_event(&self, #[indexed] caller: &ManagedAddress);
+
+    #[event("resume")]
+    fn resume_event(&self, #[indexed] caller: &ManagedAddress);
+
     #[view(getState)]
     #[storage_mapper("state")]
     fn state(&self) -> SingleValueMapper<State>;
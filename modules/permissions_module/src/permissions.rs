@@ -9,6 +9,7 @@ bitflags! {
         const OWNER = 1;
         const ADMIN = 2;
         const PAUSE = 4;
+        const KEEPER = 8;
     }
 }
 
@@ -28,24 +28,32 @@ pub trait PermissionsModule {
         let caller = self.blockchain().get_caller();
         let previous_owner_permissions = self.permissions(previous_owner.clone()).get();
 
-        self.permissions(previous_owner).clear();
-        self.permissions(caller).set(previous_owner_permissions);
+        self.permissions(previous_owner.clone()).clear();
+        self.permissions(caller.clone()).set(previous_owner_permissions);
+
+        self.permissions_event(&previous_owner, Permissions::NONE);
+        self.permissions_event(&caller, previous_owner_permissions);
     }
 
     fn set_permissions(&self, address: ManagedAddress, permissions: Permissions) {
-        self.permissions(address).set(permissions);
+        self.permissions(address.clone()).set(permissions);
+        self.permissions_event(&address, permissions);
     }
 
     fn add_permissions(&self, address: ManagedAddress, new_permissions: Permissions) {
-        self.permissions(address).update(|permissions| {
+        let permissions = self.permissions(address.clone()).update(|permissions| {
             permissions.insert(new_permissions);
+            *permissions
         });
+        self.permissions_event(&address, permissions);
     }
 
     fn remove_permissions(&self, address: ManagedAddress, permissions_to_remove: Permissions) {
-        self.permissions(address).update(|permissions| {
+        let permissions = self.permissions(address.clone()).update(|permissions| {
             permissions.remove(permissions_to_remove);
+            *permissions
         });
+        self.permissions_event(&address, permissions);
     }
 
     fn add_permissions_for_all(
@@ -83,6 +91,14 @@ pub trait PermissionsModule {
         self.require_caller_any_of(Permissions::PAUSE);
     }
 
+    fn require_caller_has_keeper_permissions(&self) {
+        self.require_caller_any_of(Permissions::KEEPER);
+    }
+
+    /// Emitted with the resulting permissions of the address every time they change.
+    #[event("permissions")]
+    fn permissions_event(&self, #[indexed] address: &ManagedAddress, permissions: Permissions);
+
     #[view(getPermissions)]
     #[storage_mapper("permissions")]
     fn permissions(&self, address: ManagedAddress) -> SingleValueMapper<Permissions>;
@@ -18,6 +18,14 @@ pub const TOKEN_3_RATE_PERCENTAGE: u64 = 150; //   1000 TOKEN3 = 15 TOKEN_OUT
 pub const TOKEN_4_RATE_PERCENTAGE: u64 = 2_300; // 1000 TOKEN4 = 230 TOKEN_OUT
 pub const TOKEN_5_RATE_PERCENTAGE: u64 = 4_670; // 1000 TOKEN5 = 467 TOKEN_OUT
 pub const AMOUNT_OUT: u64 = 1_000_000_000u64;
+pub const DEFAULT_RESERVE: u64 = 1_000_000_000_000_000_000u64;
+
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, PartialEq, Clone, Copy)]
+pub enum State {
+    Inactive,
+    Active,
+    PartialActive,
+}
 
 #[elrond_wasm::derive::contract]
 pub trait PairMock {
@@ -25,8 +33,28 @@ pub trait PairMock {
     #[init]
     fn init(&self) {}
 
+    /// Returns the token's fixed rate quote, scaled by the price multiplier of this pair if set.
+    /// Fails like a real pair if the quote is not below its reserve of the token out.
     #[view(getAmountOut)]
     fn get_amount_out_view(&self, token_in: TokenIdentifier, amount_in: BigUint) -> BigUint {
+        let amount_out = self.get_spot_amount_out(token_in.clone(), amount_in);
+        let (first_reserve, second_reserve, _) = self.get_reserves_and_total_supply().into_tuple();
+        let reserve_out = if token_in == self.first_token_id().get() { second_reserve } else { first_reserve };
+        require!(amount_out < reserve_out, "Not enough reserve");
+
+        amount_out
+    }
+
+    fn get_spot_amount_out(&self, token_in: TokenIdentifier, amount_in: BigUint) -> BigUint {
+        let amount_out = self.get_base_amount_out(token_in, amount_in);
+        if self.price_multiplier().is_empty() {
+            return amount_out;
+        }
+
+        amount_out * self.price_multiplier().get() / MAX_PERCENTAGE
+    }
+
+    fn get_base_amount_out(&self, token_in: TokenIdentifier, amount_in: BigUint) -> BigUint {
         require!(amount_in > 0u64, "Amount cannot be zero");
         if token_in == TokenIdentifier::from(ERR_TOKEN) {
             sc_panic!("Not enough reserve");
@@ -55,6 +83,93 @@ pub trait PairMock {
         BigUint::from(AMOUNT_OUT)
     }
 
+    /// Returns the spot quote scaled by the rate set for the token, without the reserve check. The mock has
+    /// no token pair, so the payment keeps the input token and only its amount is relevant.
+    #[view(getSafePriceByDefaultOffset)]
+    fn get_safe_price_by_default_offset(
+        &self,
+        _pair_address: ManagedAddress,
+        input_payment: EsdtTokenPayment
+    ) -> EsdtTokenPayment {
+        let amount_out = self.get_spot_amount_out(input_payment.token_identifier.clone(), input_payment.amount);
+        let safe_price_rate = self.safe_price_rate(&input_payment.token_identifier);
+        let amount_out = if safe_price_rate.is_empty() {
+            amount_out
+        } else {
+            amount_out * safe_price_rate.get() / MAX_PERCENTAGE
+        };
+
+        EsdtTokenPayment::new(input_payment.token_identifier, 0, amount_out)
+    }
+
+    #[endpoint(setSafePriceRate)]
+    fn set_safe_price_rate(&self, token_id: TokenIdentifier, rate: u64) {
+        self.safe_price_rate(&token_id).set(rate);
+    }
+
+    #[endpoint(setPriceMultiplier)]
+    fn set_price_multiplier(&self, rate: u64) {
+        self.price_multiplier().set(rate);
+    }
+
+    #[endpoint(setPairTokens)]
+    fn set_pair_tokens(&self, first_token_id: TokenIdentifier, second_token_id: TokenIdentifier) {
+        self.first_token_id().set(first_token_id);
+        self.second_token_id().set(second_token_id);
+    }
+
+    #[endpoint(setState)]
+    fn set_state(&self, state: State) {
+        self.state().set(Some(state));
+    }
+
+    /// Active unless set otherwise. The state is kept as an option, as Inactive encodes to empty storage.
+    #[view(getState)]
+    fn get_state(&self) -> State {
+        self.state().get().unwrap_or(State::Active)
+    }
+
+    #[endpoint(setReserves)]
+    fn set_reserves(&self, first_reserve: BigUint, second_reserve: BigUint) {
+        self.reserves().set((first_reserve, second_reserve));
+    }
+
+    /// Returns DEFAULT_RESERVE for both tokens unless set otherwise. A pair of ERR_TOKEN holds no reserves,
+    /// matching its getAmountOut panic. The total supply is not tracked.
+    #[view(getReservesAndTotalSupply)]
+    fn get_reserves_and_total_supply(&self) -> MultiValue3<BigUint, BigUint, BigUint> {
+        let err_token = TokenIdentifier::from(ERR_TOKEN);
+        if self.first_token_id().get() == err_token || self.second_token_id().get() == err_token {
+            return (BigUint::zero(), BigUint::zero(), BigUint::zero()).into();
+        }
+
+        if self.reserves().is_empty() {
+            return (BigUint::from(DEFAULT_RESERVE), BigUint::from(DEFAULT_RESERVE), BigUint::zero()).into();
+        }
+
+        let (first_reserve, second_reserve) = self.reserves().get();
+        (first_reserve, second_reserve, BigUint::zero()).into()
+    }
+
+    /// Lets the mock act as a router too, returning the pair registered for the tokens in either order.
+    #[endpoint(setPair)]
+    fn set_pair(&self, first_token_id: TokenIdentifier, second_token_id: TokenIdentifier, pair_address: ManagedAddress) {
+        self.pairs(&first_token_id, &second_token_id).set(pair_address);
+    }
+
+    #[view(getPair)]
+    fn get_pair(&self, first_token_id: TokenIdentifier, second_token_id: TokenIdentifier) -> ManagedAddress {
+        if !self.pairs(&first_token_id, &second_token_id).is_empty() {
+            return self.pairs(&first_token_id, &second_token_id).get();
+        }
+
+        if !self.pairs(&second_token_id, &first_token_id).is_empty() {
+            return self.pairs(&second_token_id, &first_token_id).get();
+        }
+
+        ManagedAddress::zero()
+    }
+
     #[payable("*")]
     #[endpoint(swapTokensFixedInput)]
     fn swap_tokens_fixed_input(
@@ -70,4 +185,50 @@ pub trait PairMock {
 
         payment
     }
-}
\ No newline at end of file
+
+    #[payable("*")]
+    #[endpoint(unwrapEgld)]
+    fn unwrap_egld(&self) {
+        let (_, amount) = self.call_value().single_fungible_esdt();
+        let caller = self.blockchain().get_caller();
+
+        self.send().direct_egld(&caller, &amount);
+    }
+
+    /// Lets the mock act as the target of a forwarded swap output, crediting the payment to original_caller.
+    #[payable("*")]
+    #[endpoint(depositFor)]
+    fn deposit_for(&self, original_caller: ManagedAddress, min_deposit: BigUint) {
+        let (_, amount) = self.call_value().single_fungible_esdt();
+        require!(amount >= min_deposit, "Deposit too low");
+
+        self.deposits(&original_caller).update(|x| *x += amount);
+    }
+
+    #[storage_mapper("price_multiplier")]
+    fn price_multiplier(&self) -> SingleValueMapper<u64>;
+
+    #[storage_mapper("safe_price_rate")]
+    fn safe_price_rate(&self, token_id: &TokenIdentifier) -> SingleValueMapper<u64>;
+
+    #[view(getFirstTokenId)]
+    #[storage_mapper("first_token_id")]
+    fn first_token_id(&self) -> SingleValueMapper<TokenIdentifier>;
+
+    #[view(getSecondTokenId)]
+    #[storage_mapper("second_token_id")]
+    fn second_token_id(&self) -> SingleValueMapper<TokenIdentifier>;
+
+    #[storage_mapper("state")]
+    fn state(&self) -> SingleValueMapper<Option<State>>;
+
+    #[storage_mapper("reserves")]
+    fn reserves(&self) -> SingleValueMapper<(BigUint, BigUint)>;
+
+    #[view(getDeposit)]
+    #[storage_mapper("deposits")]
+    fn deposits(&self, owner: &ManagedAddress) -> SingleValueMapper<BigUint>;
+
+    #[storage_mapper("pairs")]
+    fn pairs(&self, first_token_id: &TokenIdentifier, second_token_id: &TokenIdentifier) -> SingleValueMapper<ManagedAddress>;
+}
@@ -5,9 +5,9 @@
 ////////////////////////////////////////////////////
 
 // Init:                                 1
-// Endpoints:                            2
+// Endpoints:                           17
 // Async Callback (empty):               1
-// Total number of exported functions:   4
+// Total number of exported functions:  19
 
 #![no_std]
 
@@ -15,7 +15,22 @@ elrond_wasm_node::wasm_endpoints! {
     pair_mock
     (
         getAmountOut
+        getSafePriceByDefaultOffset
+        setSafePriceRate
+        setPriceMultiplier
+        setPairTokens
+        setState
+        getState
+        setReserves
+        getReservesAndTotalSupply
+        setPair
+        getPair
         swapTokensFixedInput
+        unwrapEgld
+        depositFor
+        getFirstTokenId
+        getSecondTokenId
+        getDeposit
     )
 }
 